@@ -1,23 +1,23 @@
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+use std::os::raw::{c_char, c_double, c_float, c_int, c_uint, c_long, c_ulong};
 use std::fs::{File, OpenOptions};
 use std::cmp::min;
 use std::sync::{Once, ONCE_INIT};
 extern crate libc;
 use libc::{memcpy, c_void};
 use std::mem::transmute;
-use std::collections::HashMap;
+use std::slice;
+use std::collections::{BTreeMap, HashMap};
 use flatfile::v2::schema2::Schema;
 
 extern crate flatfile;
 use flatfile::v2::schema2::Schema2;
 use flatfile::v2::filebuf::FileBuf;
-use flatfile::{ColumnType, read_schema_v2, ColumnValue, write_schema_v2, schema_write, ReadFileBuf, FileRelation, create_relation, Relation };
+use flatfile::{ColumnType, Encoding, TimeUnit, read_schema_v2, ColumnValue, ColumnValueRef, Writer, Codec, Compression, BlockOptions, WriteOptions, Checksum, ChecksumGranularity, SyncOptions, supports_bloom, read_footer, MmapBuf, train_zstd_dictionary, ReadFileBuf, FileRelation, ReverseRelation, create_relation, Relation };
 
 enum Handle {
     WriteFile {
-        f: FileBuf,
-        schema: Schema2,
+        w: Writer<FileBuf>,
         current: Vec<ColumnValue>,
     },
     ReadRelation {
@@ -89,8 +89,8 @@ pub extern fn schema2_len(handle: usize) -> c_uint {
         Handle::Freed => {
             panic!("schema2_len called on a freed handle");
         },
-        Handle::WriteFile { schema, .. } => {
-            schema.len()
+        Handle::WriteFile { w, .. } => {
+            w.schema().len()
         },
         Handle::ReadRelation { rel } => {
             rel.length()
@@ -107,6 +107,57 @@ pub extern fn schema2_destroy(handle: usize) {
     clear_handle(handle);
 }
 
+// parses "decimal(precision,scale)"
+fn parse_decimal_ctype(cts: &str) -> ColumnType {
+    let params = cts.trim_start_matches("decimal(").trim_end_matches(')');
+    let mut parts = params.split(',').map(|p| p.trim().parse::<u8>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(precision)), Some(Ok(scale)), None) => {
+            if precision == 0 || precision > 38 || scale > precision {
+                panic!("invalid decimal precision/scale in schema2_add_column: {}", cts);
+            }
+            ColumnType::Decimal { precision: precision, scale: scale }
+        },
+        _ => panic!("malformed decimal ctype in schema2_add_column: {}", cts),
+    }
+}
+
+// parses "fixed(size)"
+fn parse_fixed_ctype(cts: &str) -> ColumnType {
+    let param = cts.trim_start_matches("fixed(").trim_end_matches(')');
+    match param.trim().parse::<usize>() {
+        Ok(size) if size > 0 => ColumnType::FixedBinary { size: size },
+        _ => panic!("malformed fixed ctype in schema2_add_column: {}", cts),
+    }
+}
+
+// parses the ctype names used by schema2_add_column, lists nest as "list(u32)"
+fn parse_ctype(cts: &str) -> ColumnType {
+    match cts {
+        _ if cts.starts_with("list(") && cts.ends_with(")") => {
+            let inner = parse_ctype(&cts[5..cts.len() - 1]);
+            ColumnType::List { inner: Box::new(inner) }
+        },
+        _ if cts.starts_with("decimal(") => parse_decimal_ctype(cts),
+        _ if cts.starts_with("fixed(") => parse_fixed_ctype(cts),
+        "uuid" => ColumnType::uuid(),
+        "u32" => ColumnType::U32le,
+        "u64" => ColumnType::U64le,
+        "i32" => ColumnType::I32le,
+        "i64" => ColumnType::I64le,
+        "f32" => ColumnType::F32le,
+        "f64" => ColumnType::F64le,
+        "bool" => ColumnType::Bool,
+        "string" => ColumnType::String,
+        "bytes" => ColumnType::Bytes,
+        "timestamp_s" => ColumnType::Timestamp { unit: TimeUnit::Seconds },
+        "timestamp_ms" => ColumnType::Timestamp { unit: TimeUnit::Millis },
+        "timestamp_us" => ColumnType::Timestamp { unit: TimeUnit::Micros },
+        "timestamp_ns" => ColumnType::Timestamp { unit: TimeUnit::Nanos },
+        _ => panic!("unknown ctype in schema2_add_column: {}", cts),
+    }
+}
+
 #[no_mangle]
 pub extern fn schema2_add_column(handle: usize,
                                  name: *const c_char,
@@ -116,12 +167,7 @@ pub extern fn schema2_add_column(handle: usize,
     let cts = unsafe { CStr::from_ptr(ctype) }.to_str().unwrap();
     let handle = get_handle(handle);
 
-    let ct = match cts {
-        "u32" => ColumnType::U32le,
-        "u64" => ColumnType::U64le,
-        "string" => ColumnType::String,
-        _ => panic!("unknown ctype in schema2_add_column"),
-    };
+    let ct = parse_ctype(cts);
 
     match handle {
         Handle::Schema { schema } => {
@@ -133,6 +179,148 @@ pub extern fn schema2_add_column(handle: usize,
     };
 }
 
+#[no_mangle]
+pub extern fn schema2_set_column_timezone(handle: usize,
+                                          index: usize,
+                                          timezone: *const c_char) {
+    let tz = unsafe { CStr::from_ptr(timezone) }.to_str().unwrap();
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            match schema.ctype(index) {
+                ColumnType::Timestamp { .. } => {
+                    schema.set_timezone(index, if tz.len() > 0 { Some(tz) } else { None });
+                },
+                _ => panic!("schema2_set_column_timezone: column is not a timestamp"),
+            }
+        },
+        _ => {
+            panic!("schema2_set_column_timezone: operation not supported for this type");
+        },
+    };
+}
+
+#[no_mangle]
+pub extern fn schema2_set_column_dictionary(handle: usize,
+                                            index: usize,
+                                            dictionary: bool) {
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            match schema.ctype(index) {
+                ColumnType::String => schema.set_dictionary(index, dictionary),
+                _ => panic!("schema2_set_column_dictionary: column is not a string"),
+            }
+        },
+        _ => {
+            panic!("schema2_set_column_dictionary: operation not supported for this type");
+        },
+    };
+}
+
+// "plain", "dictionary" (string columns) or "varint" (integer columns)
+#[no_mangle]
+pub extern fn schema2_set_column_encoding(handle: usize,
+                                          index: usize,
+                                          encoding: *const c_char) {
+    let es = unsafe { CStr::from_ptr(encoding) }.to_str().unwrap();
+    let enc = match es {
+        "plain" => Encoding::Plain,
+        "dictionary" => Encoding::Dictionary,
+        "varint" => Encoding::Varint,
+        _ => panic!("unknown encoding in schema2_set_column_encoding: {}", es),
+    };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            if !enc.supports(&schema.ctype(index)) {
+                panic!("schema2_set_column_encoding: {} not supported for {:?}", es, schema.ctype(index));
+            }
+            schema.set_encoding(index, enc);
+        },
+        _ => {
+            panic!("schema2_set_column_encoding: operation not supported for this type");
+        },
+    };
+}
+
+// blocks store the values of each column together, needs blocks
+#[no_mangle]
+pub extern fn schema2_set_columnar(handle: usize, columnar: bool) {
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_columnar(columnar),
+        _ => {
+            panic!("schema2_set_columnar: operation not supported for this type");
+        },
+    };
+}
+
+// checksum of the rows, "none", "adler32", "crc32c" or "xxhash" written
+// after each group of 8 columns or once per row
+#[no_mangle]
+pub extern fn schema2_set_checksum(handle: usize,
+                                   checksum: *const c_char,
+                                   per_row: bool) {
+    let cs = unsafe { CStr::from_ptr(checksum) }.to_str().unwrap();
+    let c = match cs {
+        "none" => Checksum::None,
+        "adler32" => Checksum::Adler32,
+        "crc32c" => Checksum::Crc32c,
+        "xxhash" => Checksum::XxHash32,
+        _ => panic!("unknown checksum in schema2_set_checksum: {}", cs),
+    };
+    let granularity = if per_row { ChecksumGranularity::Row } else { ChecksumGranularity::Group };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_checksum(c, granularity),
+        _ => {
+            panic!("schema2_set_checksum: operation not supported for this type");
+        },
+    };
+}
+
+// write a sync record every 'interval' rows so that readers can skip
+// over corrupt data, 0 turns sync records off
+#[no_mangle]
+pub extern fn schema2_set_sync(handle: usize, interval: c_ulong) {
+    let sync = if interval > 0 { Some(SyncOptions::new(interval as usize)) } else { None };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_sync(sync),
+        _ => {
+            panic!("schema2_set_sync: operation not supported for this type");
+        },
+    };
+}
+
+#[no_mangle]
+pub extern fn schema2_set_index(handle: usize, rows: c_ulong) {
+    let rows = if rows > 0 { Some(rows as usize) } else { None };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_index(rows),
+        _ => {
+            panic!("schema2_set_index: operation not supported for this type");
+        },
+    };
+}
+
+#[no_mangle]
+pub extern fn schema2_set_column_bloom(handle: usize,
+                                       index: usize,
+                                       bloom: bool) {
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            if bloom && !supports_bloom(&schema.ctype(index)) {
+                panic!("schema2_set_column_bloom: not supported for the type of the column");
+            }
+            schema.set_bloom(index, bloom);
+        },
+        _ => {
+            panic!("schema2_set_column_bloom: operation not supported for this type");
+        },
+    };
+}
+
 #[no_mangle]
 pub extern fn schema2_get_column_name(handle: usize,
                                       index: usize,
@@ -140,7 +328,7 @@ pub extern fn schema2_get_column_name(handle: usize,
     let h = get_handle(handle);
     let name = match h {
         Handle::Schema { schema } => CString::new(schema.name(index)),
-        Handle::WriteFile { schema, .. } => CString::new(schema.name(index)),
+        Handle::WriteFile { w, .. } => CString::new(w.schema().name(index)),
         Handle::ReadRelation { rel } => CString::new(rel.name(index)),
         Handle::Freed => panic!("schema2_get_column_name called on a freed handle"),
     };
@@ -152,6 +340,238 @@ pub extern fn schema2_get_column_name(handle: usize,
     }
 }
 
+// copies at most size bytes of s to out, returns the length of s
+fn copy_str_out(s: &str, out: *mut c_void, size: c_ulong) -> c_ulong {
+    let u = s.as_bytes();
+    let sz = min(size as usize, u.len());
+    unsafe {
+        memcpy(out, u.as_ptr() as *const c_void, sz);
+    }
+    u.len() as c_ulong
+}
+
+#[no_mangle]
+pub extern fn schema2_set_column_description(handle: usize,
+                                             index: usize,
+                                             description: *const c_char) {
+    let d = unsafe { CStr::from_ptr(description) }.to_str().unwrap();
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            schema.set_description(index, if d.len() > 0 { Some(d) } else { None });
+        },
+        _ => {
+            panic!("schema2_set_column_description: operation not supported for this type");
+        },
+    };
+}
+
+// returns the length of the description, 0 if there is none
+#[no_mangle]
+pub extern fn schema2_get_column_description(handle: usize,
+                                             index: usize,
+                                             out: *mut c_void,
+                                             size: c_ulong) -> c_ulong {
+    let d = match get_handle(handle) {
+        Handle::Schema { schema } => schema.description(index).map(|d| d.to_owned()),
+        Handle::WriteFile { w, .. } => w.schema().description(index).map(|d| d.to_owned()),
+        Handle::ReadRelation { rel } => rel.description(index),
+        Handle::Freed => panic!("schema2_get_column_description called on a freed handle"),
+    };
+    copy_str_out(d.as_ref().map(|d| d.as_str()).unwrap_or(""), out, size)
+}
+
+#[no_mangle]
+pub extern fn schema2_set_metadata(handle: usize,
+                                   key: *const c_char,
+                                   value: *const c_char) {
+    let k = unsafe { CStr::from_ptr(key) }.to_str().unwrap();
+    let v = unsafe { CStr::from_ptr(value) }.to_str().unwrap();
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_metadata(k, v),
+        _ => {
+            panic!("schema2_set_metadata: operation not supported for this type");
+        },
+    };
+}
+
+// "default", "none", "lz4", "zstd", "zstd_dict" or "brotli", the level is ignored for "default" and "none"
+fn parse_compression(cs: &str, level: c_int) -> Compression {
+    match cs {
+        "default" => Compression::new(),
+        "none" => Compression::none(),
+        "lz4" => Compression::single(Codec::Lz4 { level: level as u32 }),
+        "zstd" => Compression::single(Codec::Zstd { level: level as i32 }),
+        "zstd_dict" => Compression::single(Codec::ZstdDict { level: level as i32 }),
+        "brotli" => Compression::single(Codec::Brotli { quality: level as u32 }),
+        _ => panic!("unknown compression: {}", cs),
+    }
+}
+
+#[no_mangle]
+pub extern fn schema2_set_column_compression(handle: usize,
+                                             index: usize,
+                                             compression: *const c_char,
+                                             level: c_int) {
+    let cs = unsafe { CStr::from_ptr(compression) }.to_str().unwrap();
+    let c = parse_compression(cs, level);
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_compression(index, Some(c)),
+        _ => {
+            panic!("schema2_set_column_compression: operation not supported for this type");
+        },
+    };
+}
+
+// dictionary used by the "zstd_dict" compression, stored in the file header
+#[no_mangle]
+pub extern fn schema2_set_zstd_dictionary(handle: usize,
+                                          data: *const c_void,
+                                          size: c_ulong) {
+    let d = unsafe { slice::from_raw_parts(data as *const u8, size as usize) };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => {
+            schema.set_zstd_dictionary(if size > 0 { Some(d.to_vec()) } else { None });
+        },
+        _ => {
+            panic!("schema2_set_zstd_dictionary: operation not supported for this type");
+        },
+    };
+}
+
+// write the rows in blocks of at most 'rows' rows or about 'bytes' bytes,
+// each block compressed as a whole, "none" turns blocks off
+#[no_mangle]
+pub extern fn schema2_set_blocks(handle: usize,
+                                 rows: c_ulong,
+                                 bytes: c_ulong,
+                                 compression: *const c_char,
+                                 level: c_int) {
+    let cs = unsafe { CStr::from_ptr(compression) }.to_str().unwrap();
+    let blocks = if cs == "none" {
+        None
+    } else {
+        Some(BlockOptions {
+            rows: rows as usize,
+            bytes: bytes as usize,
+            codec: parse_compression(cs, level).large,
+        })
+    };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_blocks(blocks),
+        _ => {
+            panic!("schema2_set_blocks: operation not supported for this type");
+        },
+    };
+}
+
+// trains the dictionary on 'count' samples stored one after another in
+// 'samples', sizes[n] is the size of the n-th sample
+#[no_mangle]
+pub extern fn schema2_train_zstd_dictionary(handle: usize,
+                                            samples: *const c_void,
+                                            sizes: *const c_ulong,
+                                            count: c_ulong,
+                                            max_size: c_ulong) -> bool {
+    let sizes = unsafe { slice::from_raw_parts(sizes, count as usize) };
+    let total = sizes.iter().map(|s| *s as usize).sum();
+    let data = unsafe { slice::from_raw_parts(samples as *const u8, total) };
+
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    for s in sizes {
+        parts.push(&data[pos..pos + *s as usize]);
+        pos += *s as usize;
+    }
+
+    let dictionary = match train_zstd_dictionary(&parts, max_size as usize) {
+        Ok(d) => d,
+        Err(e) => {
+            println!("schema2_train_zstd_dictionary: error {:?}", e);
+            return false;
+        }
+    };
+
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.set_zstd_dictionary(Some(dictionary)),
+        _ => {
+            panic!("schema2_train_zstd_dictionary: operation not supported for this type");
+        },
+    };
+    true
+}
+
+fn handle_metadata(handle: usize) -> BTreeMap<String, String> {
+    match get_handle(handle) {
+        Handle::Schema { schema } => schema.metadata.clone(),
+        Handle::WriteFile { w, .. } => w.schema().metadata.clone(),
+        Handle::ReadRelation { rel } => rel.metadata(),
+        Handle::Freed => panic!("schema2 metadata function called on a freed handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn schema2_metadata_len(handle: usize) -> c_uint {
+    handle_metadata(handle).len() as c_uint
+}
+
+// key of the n-th metadata entry, keys are sorted
+#[no_mangle]
+pub extern fn schema2_get_metadata_key(handle: usize,
+                                       n: usize,
+                                       out: *mut c_void,
+                                       size: c_ulong) -> c_ulong {
+    match handle_metadata(handle).keys().nth(n) {
+        Some(k) => copy_str_out(k, out, size),
+        None => panic!("schema2_get_metadata_key: no metadata entry {}", n),
+    }
+}
+
+// returns the length of the value, -1 if the key is not present
+#[no_mangle]
+pub extern fn schema2_get_metadata(handle: usize,
+                                   key: *const c_char,
+                                   out: *mut c_void,
+                                   size: c_ulong) -> c_long {
+    let k = unsafe { CStr::from_ptr(key) }.to_str().unwrap();
+    match handle_metadata(handle).get(k) {
+        Some(v) => copy_str_out(v, out, size) as c_long,
+        None => -1,
+    }
+}
+
+fn ctype_name(ct: &ColumnType) -> String {
+    let name = match ct {
+        ColumnType::Decimal { precision, scale } => {
+            return format!("decimal({},{})", precision, scale);
+        },
+        ColumnType::FixedBinary { size } => {
+            return format!("fixed({})", size);
+        },
+        ColumnType::List { inner } => {
+            return format!("list({})", ctype_name(inner));
+        },
+        ColumnType::U32le => "u32",
+        ColumnType::U64le => "u64",
+        ColumnType::I32le => "i32",
+        ColumnType::I64le => "i64",
+        ColumnType::F32le => "f32",
+        ColumnType::F64le => "f64",
+        ColumnType::Bool => "bool",
+        ColumnType::String => "string",
+        ColumnType::Bytes => "bytes",
+        ColumnType::Timestamp { unit: TimeUnit::Seconds } => "timestamp_s",
+        ColumnType::Timestamp { unit: TimeUnit::Millis } => "timestamp_ms",
+        ColumnType::Timestamp { unit: TimeUnit::Micros } => "timestamp_us",
+        ColumnType::Timestamp { unit: TimeUnit::Nanos } => "timestamp_ns",
+    };
+    name.to_owned()
+}
+
 #[no_mangle]
 pub extern fn schema2_get_column_type(handle: usize,
                                       index: usize,
@@ -160,16 +580,12 @@ pub extern fn schema2_get_column_type(handle: usize,
 
     let ct = match h {
         Handle::Freed => panic!("schema2_get_column_type called on a freed handle"),
-        Handle::WriteFile { schema, .. } => schema.ctype(index),
+        Handle::WriteFile { w, .. } => w.schema().ctype(index),
         Handle::Schema { schema } => schema.ctype(index),
         Handle::ReadRelation { rel, .. } => rel.ctype(index),
     };
 
-    let name = match ct {
-        ColumnType::U32le => "u32",
-        ColumnType::U64le => "u64",
-        ColumnType::String => "string",
-    };
+    let name = ctype_name(&ct);
 
     let s = CString::new(name).unwrap();
 
@@ -184,7 +600,7 @@ pub extern fn schema2_get_column_nullable(handle: usize,
     let h = get_handle(handle);
     match h {
         Handle::Freed => panic!("schema2_get_column_nullable called on a freed handle"),
-        Handle::WriteFile { schema, .. } => schema.nullable(index),
+        Handle::WriteFile { w, .. } => w.schema().nullable(index),
         Handle::ReadRelation { rel } => rel.nullable(index),
         Handle::Schema { schema } => schema.nullable(index),
     }
@@ -203,8 +619,7 @@ pub extern fn writef_create(name: *const c_char,
 
     match get_handle(schema_handle) {
         Handle::Schema { schema } => {
-            let mut filebuf = FileBuf::new(f, 4096);
-            write_schema_v2(&mut filebuf, &schema);
+            let filebuf = FileBuf::new(f, 4096);
 
             // create a row to store values for write
             let mut writevec = Vec::new();
@@ -213,8 +628,7 @@ pub extern fn writef_create(name: *const c_char,
             }
 
             let h = put_handle(Handle::WriteFile {
-                f: filebuf,
-                schema: schema.to_owned(),
+                w: Writer::create(filebuf, &schema, WriteOptions::new()),
                 current: writevec,
             });
 
@@ -236,11 +650,28 @@ pub extern fn writef_close(handle: c_uint) {
 pub extern fn writef_flush(handle: c_uint) -> bool {
     let h = handle as usize; // TBD
     match get_handle(h) {
-        Handle::WriteFile { f, .. } => f.flush_all(),
+        Handle::WriteFile { w, .. } => {
+            w.flush_block();
+            w.get_mut().flush_all()
+        },
         _ => panic!("writef_flush() called with no write handle")
     }
 }
 
+// compression of the columns without a compression of their own
+#[no_mangle]
+pub extern fn writef_set_compression(handle: c_uint,
+                                     compression: *const c_char,
+                                     level: c_int) {
+    let cs = unsafe { CStr::from_ptr(compression) }.to_str().unwrap();
+    let c = parse_compression(cs, level);
+
+    match get_handle(handle as usize) {
+        Handle::WriteFile { w, .. } => w.options_mut().compression = c,
+        _ => panic!("writef_set_compression() called with no write handle")
+    }
+}
+
 #[no_mangle]
 pub extern fn readf_clone_schema(handle: c_uint) -> c_uint {
     handle
@@ -276,7 +707,7 @@ pub extern fn writef_open(name: *const c_char) -> c_int {
     }
 
     // reopen in append mode
-    let f = match OpenOptions::new().append(true).open(fname) {
+    let f = match OpenOptions::new().append(true).read(true).open(fname) {
         Ok(h) => h,
         Err(e) => {
             println!("writef_open: (append): error {:?}", e);
@@ -284,11 +715,39 @@ pub extern fn writef_open(name: *const c_char) -> c_int {
         }
     };
 
+    let mut size = match f.metadata() {
+        Ok(md) => md.len() as usize,
+        Err(e) => {
+            println!("writef_open: (append): error {:?}", e);
+            return -1;
+        }
+    };
+
+    // the footer is removed and written again with the new rows on close
+    let mut index = None;
+    if sch.index_rows.is_some() {
+        let footer = {
+            let mut m = MmapBuf::new(f.try_clone().unwrap());
+            read_footer(&mut m, &sch, size)
+        };
+        if let Some((footer, start)) = footer {
+            if let Err(e) = f.set_len(start as u64) {
+                println!("writef_open: (append): error {:?}", e);
+                return -1;
+            }
+            size = start;
+            index = Some(footer);
+        }
+    }
     let filebuf = FileBuf::new(f, 4096);
 
+    // the first row written resets the dictionaries already in the file
+    let mut w = Writer::append(filebuf, size, &sch, WriteOptions::new());
+    if let Some(index) = index {
+        w.set_index(index);
+    }
     let h = put_handle(Handle::WriteFile {
-        f: filebuf,
-        schema: sch,
+        w: w,
         current: writevec,
     });
 
@@ -333,6 +792,25 @@ pub extern fn readf_open(name: *const c_char) -> c_int {
     }
 }
 
+// rows are read from the last to the first, the file needs an index or
+// sync records
+#[no_mangle]
+pub extern fn readf_open_reverse(name: *const c_char) -> c_int {
+    let fname = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+
+    match FileRelation::new(fname).and_then(ReverseRelation::new) {
+        Ok(rel) => {
+            let h = put_handle(Handle::ReadRelation { rel: Box::new(rel) });
+            h as c_int
+        },
+        Err(e) => {
+            println!("readf_open_reverse(): error={:?} fname={}", e, fname);
+            let r: i32 = -1;
+            r as c_int
+        }
+    }
+}
+
 #[no_mangle]
 pub extern fn readf_close(handle: c_uint) {
     let h = handle as usize; // TBD
@@ -394,15 +872,117 @@ pub extern fn readf_row_get_u64(fhandle: c_uint, index: c_uint) -> c_ulong {
 }
 
 #[no_mangle]
-pub extern fn readf_row_get_string_len(fhandle: c_uint, index: c_uint) -> c_ulong {
+pub extern fn readf_row_get_i32(fhandle: c_uint, index: c_uint) -> c_int {
+    let value = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::I32 { v } => *v,
+                _ => panic!("column type not i32, index: {}, debug: {:?}", index, rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_i32 called on a non-read handle"),
+    };
+    value as c_int
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_i64(fhandle: c_uint, index: c_uint) -> c_long {
+    let result = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::I64 { v } => *v,
+                _ => panic!("column type not i64 but {:?}", rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_i64 called on a non-read handle"),
+    };
+    result as c_long
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_f32(fhandle: c_uint, index: c_uint) -> c_float {
+    let value = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::F32 { v } => *v,
+                _ => panic!("column type not f32, index: {}, debug: {:?}", index, rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_f32 called on a non-read handle"),
+    };
+    value as c_float
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_f64(fhandle: c_uint, index: c_uint) -> c_double {
+    let result = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::F64 { v } => *v,
+                _ => panic!("column type not f64 but {:?}", rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_f64 called on a non-read handle"),
+    };
+    result as c_double
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_bool(fhandle: c_uint, index: c_uint) -> bool {
     match get_handle(fhandle as usize) {
         Handle::ReadRelation { rel } => {
             match rel.value(index as usize) {
-                ColumnValue::String { ref v } => {
-                    let u = v.as_str().as_bytes();
+                ColumnValue::Bool { v } => *v,
+                _ => panic!("column type not bool but {:?}", rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_bool called on a non-read handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_timestamp(fhandle: c_uint, index: c_uint) -> c_long {
+    let result = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::Timestamp { v, .. } => *v,
+                _ => panic!("column type not timestamp but {:?}", rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_timestamp called on a non-read handle"),
+    };
+    result as c_long
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_decimal(fhandle: c_uint, index: c_uint) -> c_long {
+    let result = match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::Decimal { v, .. } => {
+                    if *v < (i64::min_value() as i128) || *v > (i64::max_value() as i128) {
+                        panic!("decimal value does not fit 64 bits, index: {}", index);
+                    }
+                    *v as i64
+                },
+                _ => panic!("column type not decimal but {:?}", rel.ctype(index as usize)),
+            }
+        },
+        _ => panic!("readf_row_get_decimal called on a non-read handle"),
+    };
+    result as c_long
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_string_len(fhandle: c_uint, index: c_uint) -> c_ulong {
+    match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value_ref(index as usize) {
+                ColumnValueRef::String { v } => {
+                    let u = v.as_bytes();
                     u.len() as c_ulong
                 }
-                ColumnValue::Null => {
+                ColumnValueRef::Value { v: ColumnValue::Null } => {
                     0
                 },
                 _ => panic!("column type not string rh={} i={} sch_len={}", fhandle, index, rel.length())
@@ -416,16 +996,16 @@ pub extern fn readf_row_get_string_len(fhandle: c_uint, index: c_uint) -> c_ulon
 pub extern fn readf_row_get_string(fhandle: c_uint, index: c_uint, out: *mut c_void, size: c_ulong) -> c_ulong {
     match get_handle(fhandle as usize) {
         Handle::ReadRelation { rel } => {
-            match rel.value(index as usize) {
-                ColumnValue::String { ref v } => {
-                    let u = v.as_str().as_bytes();
+            match rel.value_ref(index as usize) {
+                ColumnValueRef::String { v } => {
+                    let u = v.as_bytes();
                     let sz = min(size as usize, u.len());
                     unsafe {
                         memcpy(out, u.as_ptr() as *const c_void, sz);
                     }
                     u.len() as c_ulong
                 }
-                ColumnValue::Null => {
+                ColumnValueRef::Value { v: ColumnValue::Null } => {
                     0
                 },
                 _ => panic!("column type not string rh={} i={} sch_len={}", fhandle, index, rel.length())
@@ -435,6 +1015,44 @@ pub extern fn readf_row_get_string(fhandle: c_uint, index: c_uint, out: *mut c_v
     }
 }
 
+#[no_mangle]
+pub extern fn readf_row_get_bytes_len(fhandle: c_uint, index: c_uint) -> c_ulong {
+    match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::Bytes { ref v } => v.len() as c_ulong,
+                ColumnValue::Null => {
+                    0
+                },
+                _ => panic!("column type not bytes rh={} i={} sch_len={}", fhandle, index, rel.length())
+            }
+        },
+        _ => panic!("readf_row_get_bytes_len called on a non-read handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn readf_row_get_bytes(fhandle: c_uint, index: c_uint, out: *mut c_void, size: c_ulong) -> c_ulong {
+    match get_handle(fhandle as usize) {
+        Handle::ReadRelation { rel } => {
+            match rel.value(index as usize) {
+                ColumnValue::Bytes { ref v } => {
+                    let sz = min(size as usize, v.len());
+                    unsafe {
+                        memcpy(out, v.as_ptr() as *const c_void, sz);
+                    }
+                    v.len() as c_ulong
+                }
+                ColumnValue::Null => {
+                    0
+                },
+                _ => panic!("column type not bytes rh={} i={} sch_len={}", fhandle, index, rel.length())
+            }
+        }
+        _ => panic!("readf_row_get_bytes called on a non-read handle")
+    }
+}
+
 #[no_mangle]
 pub extern fn writef_row_start(fhandle: c_uint) {
     match get_handle(fhandle as usize) {
@@ -450,9 +1068,7 @@ pub extern fn writef_row_start(fhandle: c_uint) {
 #[no_mangle]
 pub extern fn writef_row_end(fhandle: c_uint) -> bool {
     match get_handle(fhandle as usize) {
-        Handle::WriteFile { ref mut f, ref current, schema } => {
-            schema_write(f, current.as_slice(), &schema)
-        }
+        Handle::WriteFile { w, current } => w.write(current.as_slice()),
         _ => panic!("writef_row_end called on a non-write handle"),
     }
 }
@@ -460,7 +1076,8 @@ pub extern fn writef_row_end(fhandle: c_uint) -> bool {
 #[no_mangle]
 pub extern fn writef_row_set_u32(fhandle: c_uint, index: c_uint, value: c_uint) {
     match get_handle(fhandle as usize) {
-        Handle::WriteFile { schema, current, .. } => {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
             if (index as usize) >= schema.len() {
                 panic!("row_write_set_u32 uindex > schema.len()");
             }
@@ -476,7 +1093,8 @@ pub extern fn writef_row_set_u32(fhandle: c_uint, index: c_uint, value: c_uint)
 #[no_mangle]
 pub extern fn writef_row_set_u64(fhandle: c_uint, index: c_uint, value: c_ulong) {
     match get_handle(fhandle as usize) {
-        Handle::WriteFile { schema, current, .. } => {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
             if (index as usize) >= schema.len() {
                 panic!("row_write_set_u64 uindex > schema.len()");
             }
@@ -489,10 +1107,132 @@ pub extern fn writef_row_set_u64(fhandle: c_uint, index: c_uint, value: c_ulong)
     }
 }
 
+#[no_mangle]
+pub extern fn writef_row_set_i32(fhandle: c_uint, index: c_uint, value: c_int) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_i32 uindex > schema.len()");
+            }
+            if schema.ctype(index as usize) != ColumnType::I32le {
+                panic!("row_write_set_i32 incorrect type");
+            }
+            current[index as usize] = ColumnValue::I32 { v: value as i32 };
+        },
+        _ => panic!("writef_row_set_i32 called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_i64(fhandle: c_uint, index: c_uint, value: c_long) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_i64 uindex > schema.len()");
+            }
+            if schema.ctype(index as usize) != ColumnType::I64le {
+                panic!("row_write_set_i64 incorrect type");
+            }
+            current[index as usize] = ColumnValue::I64 { v: value as i64 };
+        },
+        _ => panic!("writef_row_set_i64 called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_f32(fhandle: c_uint, index: c_uint, value: c_float) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_f32 uindex > schema.len()");
+            }
+            if schema.ctype(index as usize) != ColumnType::F32le {
+                panic!("row_write_set_f32 incorrect type");
+            }
+            current[index as usize] = ColumnValue::F32 { v: value as f32 };
+        },
+        _ => panic!("writef_row_set_f32 called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_f64(fhandle: c_uint, index: c_uint, value: c_double) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_f64 uindex > schema.len()");
+            }
+            if schema.ctype(index as usize) != ColumnType::F64le {
+                panic!("row_write_set_f64 incorrect type");
+            }
+            current[index as usize] = ColumnValue::F64 { v: value as f64 };
+        },
+        _ => panic!("writef_row_set_f64 called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_bool(fhandle: c_uint, index: c_uint, value: bool) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_bool uindex > schema.len()");
+            }
+            if schema.ctype(index as usize) != ColumnType::Bool {
+                panic!("row_write_set_bool incorrect type");
+            }
+            current[index as usize] = ColumnValue::Bool { v: value };
+        },
+        _ => panic!("writef_row_set_bool called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_timestamp(fhandle: c_uint, index: c_uint, value: c_long) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_timestamp uindex > schema.len()");
+            }
+            let unit = match schema.ctype(index as usize) {
+                ColumnType::Timestamp { unit } => unit,
+                _ => panic!("row_write_set_timestamp incorrect type"),
+            };
+            current[index as usize] = ColumnValue::Timestamp { v: value as i64, unit: unit };
+        },
+        _ => panic!("writef_row_set_timestamp called on a non-write handle"),
+    }
+}
+
+#[no_mangle]
+pub extern fn writef_row_set_decimal(fhandle: c_uint, index: c_uint, value: c_long) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_decimal uindex > schema.len()");
+            }
+            let scale = match schema.ctype(index as usize) {
+                ColumnType::Decimal { scale, .. } => scale,
+                _ => panic!("row_write_set_decimal incorrect type"),
+            };
+            current[index as usize] = ColumnValue::Decimal { v: value as i128, scale: scale };
+        },
+        _ => panic!("writef_row_set_decimal called on a non-write handle"),
+    }
+}
+
 #[no_mangle]
 pub extern fn writef_row_set_string(fhandle: c_uint, index: c_uint, value: *const c_char) {
     match get_handle(fhandle as usize) {
-        Handle::WriteFile { schema, current, .. } => {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
             if (index as usize) >= schema.len() {
                 panic!("row_write_set_string uindex > schema.len()");
             }
@@ -506,6 +1246,34 @@ pub extern fn writef_row_set_string(fhandle: c_uint, index: c_uint, value: *cons
     }
 }
 
+#[no_mangle]
+pub extern fn writef_row_set_bytes(fhandle: c_uint, index: c_uint, value: *const c_void, size: c_ulong) {
+    match get_handle(fhandle as usize) {
+        Handle::WriteFile { w, current } => {
+            let schema = w.schema();
+            if (index as usize) >= schema.len() {
+                panic!("row_write_set_bytes uindex > schema.len()");
+            }
+            match schema.ctype(index as usize) {
+                ColumnType::Bytes => {},
+                ColumnType::FixedBinary { size: fsize } => {
+                    if fsize != size as usize {
+                        panic!("row_write_set_bytes size {} does not match fixed({})", size, fsize);
+                    }
+                },
+                _ => panic!("row_write_set_bytes incorrect type"),
+            }
+            let value = if size == 0 {
+                Vec::new()
+            } else {
+                unsafe { slice::from_raw_parts(value as *const u8, size as usize) }.to_vec()
+            };
+            current[index as usize] = ColumnValue::Bytes { v: value };
+        },
+        _ => panic!("writef_row_set_bytes called on a non-write handle"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
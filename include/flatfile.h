@@ -13,7 +13,56 @@ int schema2_len(unsigned int schema_handle);
 void schema2_destroy(unsigned int schema_handle);
 int schema2_add_column(unsigned long schema_handle, char const* name,
                        char const* ctype, _Bool nullable);
+void schema2_set_column_timezone(unsigned long schema_handle, int index,
+                                 char const* timezone);
+void schema2_set_column_dictionary(unsigned long schema_handle, int index,
+                                   _Bool dictionary);
+void schema2_set_column_encoding(unsigned long schema_handle, int index,
+                                 char const* encoding);
+/* "default", "none", "lz4", "zstd", "zstd_dict" or "brotli" with a
+ * compression level (the quality for brotli) */
+void schema2_set_column_compression(unsigned long schema_handle, int index,
+                                    char const* compression, int level);
+/* dictionary for "zstd_dict", stored in the file header */
+void schema2_set_zstd_dictionary(unsigned long schema_handle, void const* data,
+                                 unsigned long size);
+/* samples are stored one after another, sizes[n] is the size of sample n */
+bool schema2_train_zstd_dictionary(unsigned long schema_handle,
+                                   void const* samples,
+                                   unsigned long const* sizes,
+                                   unsigned long count,
+                                   unsigned long max_size);
+/* rows are written in blocks compressed as a whole, "none" disables blocks */
+void schema2_set_blocks(unsigned long schema_handle, unsigned long rows,
+                        unsigned long bytes, char const* compression,
+                        int level);
+/* blocks store the values of each column together, needs blocks */
+void schema2_set_columnar(unsigned long schema_handle, bool columnar);
+/* "none", "adler32", "crc32c" or "xxhash", once per row or per 8 columns */
+void schema2_set_checksum(unsigned long schema_handle, char const* checksum,
+                          bool per_row);
+/* a sync record every interval rows lets readers skip corrupt data, 0 = off */
+void schema2_set_sync(unsigned long schema_handle, unsigned long interval);
+/* a footer with row counts and statistics every rows rows, 0 = no footer */
+void schema2_set_index(unsigned long schema_handle, unsigned long rows);
+/* a Bloom filter per index block for lookups of single values, needs an index */
+void schema2_set_column_bloom(unsigned long schema_handle, int index,
+                              bool bloom);
 int schema2_get_column_name(unsigned int schema_handle, int index, char* buf);
+void schema2_set_column_description(unsigned long schema_handle, int index,
+                                    char const* description);
+/* returns the length of the description, copies at most size bytes */
+unsigned long schema2_get_column_description(unsigned int schema_handle,
+                                             int index, void* out,
+                                             unsigned long size);
+void schema2_set_metadata(unsigned long schema_handle, char const* key,
+                          char const* value);
+unsigned int schema2_metadata_len(unsigned int schema_handle);
+unsigned long schema2_get_metadata_key(unsigned int schema_handle, int n,
+                                       void* out, unsigned long size);
+/* returns the length of the value or -1 if the key is not present */
+long schema2_get_metadata(unsigned int schema_handle, char const* key,
+                          void* out, unsigned long size);
 
 int schema2_get_column_type(unsigned int schema_handle, int index, char* buf);
 
@@ -33,23 +82,49 @@ void writef_row_set_u32(unsigned int handle, unsigned int index,
                         unsigned int value);
 void writef_row_set_u64(unsigned int handle, unsigned int index,
                         unsigned long value);
+void writef_row_set_i32(unsigned int handle, unsigned int index, int value);
+void writef_row_set_i64(unsigned int handle, unsigned int index, long value);
+void writef_row_set_f32(unsigned int handle, unsigned int index, float value);
+void writef_row_set_f64(unsigned int handle, unsigned int index, double value);
+void writef_row_set_bool(unsigned int handle, unsigned int index, bool value);
+void writef_row_set_timestamp(unsigned int handle, unsigned int index,
+                              long value);
+/* unscaled value, e.g. 12345 for 123.45 in a decimal(10,2) column */
+void writef_row_set_decimal(unsigned int handle, unsigned int index,
+                            long value);
 void writef_row_set_string(unsigned int handle, unsigned int index,
                            char const* s);
+void writef_row_set_bytes(unsigned int handle, unsigned int index,
+                          void const* data, unsigned long size);
 bool writef_row_end(unsigned int handle);
 bool writef_flush(unsigned int handle);
+void writef_set_compression(unsigned int handle, char const* compression,
+                            int level);
 
 unsigned long readf_row_get_string_len(unsigned int fhandle,
                                        unsigned int index);
 unsigned long readf_row_get_string(unsigned int fhandle, unsigned int index,
                                    void* out, unsigned long size);
+unsigned long readf_row_get_bytes_len(unsigned int fhandle,
+                                      unsigned int index);
+unsigned long readf_row_get_bytes(unsigned int fhandle, unsigned int index,
+                                  void* out, unsigned long size);
 unsigned long readf_row_get_u64(unsigned int fhandle, unsigned int index);
 unsigned int readf_row_get_u32(unsigned int fhandle, unsigned int index);
+long readf_row_get_i64(unsigned int fhandle, unsigned int index);
+int readf_row_get_i32(unsigned int fhandle, unsigned int index);
+double readf_row_get_f64(unsigned int fhandle, unsigned int index);
+float readf_row_get_f32(unsigned int fhandle, unsigned int index);
+bool readf_row_get_bool(unsigned int fhandle, unsigned int index);
+long readf_row_get_timestamp(unsigned int fhandle, unsigned int index);
+long readf_row_get_decimal(unsigned int fhandle, unsigned int index);
 unsigned int readf_row_is_null(unsigned int fhandle, unsigned int index);
 unsigned int readf_row_start(unsigned int fhandle);
 void readf_close(unsigned int fhandle);
 void readf_row_end(unsigned int fhandle);
 int readf_open(char const* name);
 int readf_open_relation(char const* name, char const* reldef);
+int readf_open_reverse(char const* name);
 unsigned int readf_clone_schema(unsigned int fhandle);
 
 #endif  // FLATFILE_H_INCLUDED
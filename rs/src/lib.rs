@@ -3,9 +3,19 @@ pub mod v1;
 pub mod v2;
 pub use v1::schema::Metadata;
 pub use v1::parse::parse_string;
-pub use types::{ColumnType, ColumnValue, Relation};
+pub use types::{Batch, ColumnType, ColumnValue, ColumnValueRef, ColumnVector, Relation, TimeUnit};
 pub use v2::mmapbuf::MmapBuf;
-pub use v2::write2::{read_schema_v2, schema_read_row, write_schema_v2, schema_write};
+pub use v2::write2::{read_schema_v2, schema_read_row, schema_read_row_dict, write_schema_v2, schema_write, schema_write_with};
+pub use v2::dict::Dictionaries;
+pub use v2::schema2::Encoding;
+pub use v2::codec::{BlockOptions, Codec, Compression, WriteOptions, train_zstd_dictionary};
+pub use v2::checksum::{Checksum, ChecksumGranularity};
+pub use v2::sync::SyncOptions;
+pub use v2::index::{BlockIndex, ColumnStats, FileIndex};
+pub use v2::bloom::{Bloom, supports_bloom};
+pub use v2::write2::read_footer;
+pub use v2::writer::Writer;
+pub use v2::reader::RowReader;
 pub use v2::filebuf::{FileBuf, ReadFileBuf};
-pub use v2::rel::{FileRelation, create_relation};
+pub use v2::rel::{FileRelation, ReverseRelation, create_relation};
 pub use v2::err::SchemaReadError;
@@ -1,8 +1,48 @@
-#[derive(PartialEq,Clone,Copy, Debug)]
+use std::collections::BTreeMap;
+use std::mem;
+use v2::ast::Expr;
+
+// not Copy, a list holds its element type in a Box
+#[derive(PartialEq, Clone, Debug)]
 pub enum ColumnType {
     U32le,
     U64le,
+    I32le,
+    I64le,
+    F32le,
+    F64le,
+    Bool,
+    Timestamp {
+        unit: TimeUnit,
+    },
+    Decimal {
+        precision: u8, // up to 18 digits are stored in 8 bytes, else 16
+        scale: u8,
+    },
     String,
+    Bytes,
+    FixedBinary {
+        size: usize, // values are ColumnValue::Bytes of exactly this length
+    },
+    List {
+        inner: Box<ColumnType>, // elements are never null
+    },
+}
+
+impl ColumnType {
+    // uuids are stored as 16 raw bytes
+    pub fn uuid() -> ColumnType {
+        ColumnType::FixedBinary { size: 16 }
+    }
+}
+
+// resolution of a timestamp column, stored in the schema header
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TimeUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
 }
 
 #[derive(Clone, Debug)]
@@ -28,9 +68,202 @@ pub enum ColumnValue {
     U64 {
         v: u64,
     },
+    I32 {
+        v: i32,
+    },
+    I64 {
+        v: i64,
+    },
+    F32 {
+        v: f32,
+    },
+    F64 {
+        v: f64,
+    },
+    Bool {
+        v: bool,
+    },
+    Timestamp {
+        v: i64, // ticks of 'unit' since the unix epoch
+        unit: TimeUnit,
+    },
+    Decimal {
+        v: i128, // unscaled, the value is v / 10^scale
+        scale: u8,
+    },
     String {
         v: String,
     },
+    Bytes {
+        v: Vec<u8>,
+    },
+    List {
+        v: Vec<ColumnValue>,
+    },
+}
+
+// a value borrowed from a relation, strings of files read through a
+// mapping point into the file
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ColumnValueRef<'a> {
+    String {
+        v: &'a str,
+    },
+    Value {
+        v: &'a ColumnValue, // any other value
+    },
+}
+
+impl<'a> ColumnValueRef<'a> {
+    pub fn to_value(&self) -> ColumnValue {
+        match self {
+            ColumnValueRef::String { v } => ColumnValue::String { v: v.to_string() },
+            ColumnValueRef::Value { v } => (*v).clone(),
+        }
+    }
+}
+
+impl<'a> From<&'a ColumnValue> for ColumnValueRef<'a> {
+    fn from(value: &'a ColumnValue) -> ColumnValueRef<'a> {
+        match value {
+            ColumnValue::String { v } => ColumnValueRef::String { v },
+            _ => ColumnValueRef::Value { v: value },
+        }
+    }
+}
+
+// the values of a column for the rows of a batch
+#[derive(Clone, Debug, Default)]
+pub struct ColumnVector {
+    pub values: Vec<ColumnValue>, // Null where the bit in 'nulls' is set
+    pub nulls: Vec<u8>, // a bit per row, set if the value is null
+}
+
+impl ColumnVector {
+    pub fn new() -> ColumnVector {
+        ColumnVector {
+            values: Vec::new(),
+            nulls: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_null(&self, row: usize) -> bool {
+        self.nulls[row / 8] & (1 << (row % 8)) != 0
+    }
+
+    pub fn push(&mut self, value: ColumnValue) {
+        let row = self.values.len();
+        if row == self.nulls.len() * 8 {
+            self.nulls.push(0);
+        }
+        if value == ColumnValue::Null {
+            self.nulls[row / 8] |= 1 << (row % 8);
+        }
+        self.values.push(value);
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.nulls.clear();
+    }
+
+    // move 'values' to the end of the column, an empty column takes the
+    // vector and leaves its own in 'values'
+    pub fn append(&mut self, values: &mut Vec<ColumnValue>) {
+        let row = self.values.len();
+        if row == 0 {
+            mem::swap(&mut self.values, values);
+        } else {
+            self.values.append(values);
+        }
+        self.set_nulls(row);
+    }
+
+    // keep the rows for which 'keep' is set
+    pub fn retain(&mut self, keep: &[bool]) {
+        let mut row = 0;
+        self.values.retain(|_| {
+            row += 1;
+            keep[row - 1]
+        });
+        self.nulls.clear();
+        self.set_nulls(0);
+    }
+
+    // the bits of the rows from 'row' on, which are not set yet
+    fn set_nulls(&mut self, row: usize) {
+        self.nulls.resize(self.values.len().div_ceil(8), 0);
+        for (n, value) in self.values.iter().enumerate().skip(row) {
+            if *value == ColumnValue::Null {
+                self.nulls[n / 8] |= 1 << (n % 8);
+            }
+        }
+    }
+}
+
+// rows read by Relation::read_batch into vectors owned by the caller,
+// the vectors are reused by the next batch
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    pub columns: Vec<ColumnVector>,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch {
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.columns.first().map_or(0, |c| c.len())
+    }
+
+    pub fn value(&self, row: usize, col: usize) -> &ColumnValue {
+        &self.columns[col].values[row]
+    }
+
+    // remove the rows, the batch is filled with rows of 'columns' columns
+    pub fn reset(&mut self, columns: usize) {
+        self.columns.resize(columns, ColumnVector::new());
+        for c in self.columns.iter_mut() {
+            c.clear();
+        }
+    }
+
+    // keep the rows for which 'keep' is set
+    pub fn retain(&mut self, keep: &[bool]) {
+        for c in self.columns.iter_mut() {
+            c.retain(keep);
+        }
+    }
+
+    // move the rows of 'other' to the end of the batch, 'other' is left
+    // empty
+    pub fn append(&mut self, other: &mut Batch) {
+        for (c, o) in self.columns.iter_mut().zip(other.columns.iter_mut()) {
+            c.append(&mut o.values);
+            o.clear();
+        }
+    }
+}
+
+// fill 'batch' with up to 'max' rows read one by one, for relations
+// that can not do better
+pub fn read_rows<R: Relation + ?Sized>(rel: &mut R, batch: &mut Batch, max: usize) -> usize {
+    batch.reset(rel.length());
+    let mut rows = 0;
+    while rows < max && rel.read() {
+        for (n, column) in batch.columns.iter_mut().enumerate() {
+            column.push(rel.value(n).clone());
+        }
+        rows += 1;
+    }
+    rows
 }
 
 // change from AOS to SOA?
@@ -50,6 +283,36 @@ pub trait Relation {
     fn name(&self, n: usize) -> String;
     fn ctype(&self, n: usize) -> ColumnType;
     fn nullable(&self, n: usize) -> bool;
+    fn description(&self, _n: usize) -> Option<String> {
+        None
+    }
+    // time zone of a timestamp column
+    fn timezone(&self, _n: usize) -> Option<String> {
+        None
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+    // strings that read() left in the file are copied out of it
     fn value(&self, n: usize) -> &ColumnValue;
+    // the value without copying strings that are still in the file, a
+    // relation may leave them there until value() is called
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        ColumnValueRef::from(self.value(n))
+    }
+    // read up to 'max' rows into 'batch', replacing the rows in it. The
+    // number of rows read is returned, 0 after the last row. value() is
+    // not valid after this
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        read_rows(self, batch, max)
+    }
+    // only rows for which e holds will be used, the relation may skip
+    // the others without reading them
+    fn restrict(&mut self, _e: &Expr) {
+    }
+    // only these columns will be read with value(), the relation may skip
+    // reading the others. Further calls add to the columns
+    fn require(&mut self, _columns: &[usize]) {
+    }
     fn dump_debug_info(&self);
 }
@@ -42,6 +42,9 @@ pub fn schema_write<W: Write>(
                         panic!("Column {} expected string received u64le", names[validx]);
                     }
                     ColumnValue::Null => {}
+                    _ => {
+                        panic!("Column {} received a value not supported by v1", names[validx]);
+                    }
                 }
             }
             ColumnType::U32le => {
@@ -54,6 +57,9 @@ pub fn schema_write<W: Write>(
                     }
                     ColumnValue::U32 { .. } => {}
                     ColumnValue::Null => {}
+                    _ => {
+                        panic!("Column {} received a value not supported by v1", names[validx]);
+                    }
                 }
             }
             ColumnType::U64le => {
@@ -66,8 +72,14 @@ pub fn schema_write<W: Write>(
                     }
                     ColumnValue::U64 { .. } => {}
                     ColumnValue::Null => {}
+                    _ => {
+                        panic!("Column {} received a value not supported by v1", names[validx]);
+                    }
                 }
             }
+            ref ct => {
+                panic!("Column {} has type {:?} which is not supported by v1", names[validx], ct);
+            }
         }
     }
 
@@ -213,6 +225,7 @@ pub fn schema_write<W: Write>(
                     adler.update_buffer(&buf);
                 }
                 &ColumnValue::Null => {}
+                _ => {}
             }
         }
 
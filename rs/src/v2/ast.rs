@@ -1,13 +1,18 @@
-use types::{ColumnValue, Relation};
+use types::{ColumnType, ColumnValue, ColumnValueRef, Relation, TimeUnit};
+use v2::timestamp::{parse_iso8601, parse_iso8601_in, to_nanos};
+use std::convert::TryFrom;
+use v2::decimal;
+use std::cmp::{max, Ordering};
 use std::collections::VecDeque;
+use std::borrow::Cow;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Value {
     Ref { col: usize },
     Val { val: ColumnValue },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Expr {
     Equal {
       l: Value,
@@ -17,6 +22,22 @@ pub enum Expr {
       l: Value,
       r: Value,
     },
+    Less {
+      l: Value,
+      r: Value,
+    },
+    LessEqual {
+      l: Value,
+      r: Value,
+    },
+    Greater {
+      l: Value,
+      r: Value,
+    },
+    GreaterEqual {
+      l: Value,
+      r: Value,
+    },
     IsNull {
       l: Value,
     },
@@ -80,45 +101,213 @@ pub fn parse_expr(s: &[u8]) -> Box<Expr> {
     Box::new(x)
 }
 
-fn eq(rel: &Relation, l: &Value, r: &Value, isnull: bool) -> bool {
-    let lv = match l {
-        Value::Val { val } => val,
-        Value::Ref { col } => rel.value(*col),
+// widen any integer value so that signed and unsigned columns compare
+pub fn int_value(v: &ColumnValue) -> Option<i128> {
+    match v {
+        ColumnValue::U32 { v } => Some(*v as i128),
+        ColumnValue::U64 { v } => Some(*v as i128),
+        ColumnValue::I32 { v } => Some(*v as i128),
+        ColumnValue::I64 { v } => Some(*v as i128),
+        _ => None,
+    }
+}
+
+fn float_value(v: &ColumnValue) -> Option<f64> {
+    match v {
+        ColumnValue::F32 { v } => Some(*v as f64),
+        ColumnValue::F64 { v } => Some(*v),
+        ColumnValue::Decimal { v, scale } => Some(decimal::to_f64(*v, *scale)),
+        _ => int_value(v).map(|i| i as f64),
+    }
+}
+
+fn is_float(v: &ColumnValue) -> bool {
+    match v {
+        ColumnValue::F32 { .. } | ColumnValue::F64 { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_timestamp(v: &ColumnValue) -> bool {
+    match v {
+        ColumnValue::Timestamp { .. } => true,
+        _ => false,
+    }
+}
+
+// timestamps compare in nanoseconds, strings are read as ISO-8601
+pub fn timestamp_value(v: &ColumnValue) -> Option<i128> {
+    match v {
+        ColumnValue::Timestamp { v, unit } => Some(to_nanos(*v, *unit)),
+        ColumnValue::String { v } => parse_iso8601(v),
+        _ => None,
+    }
+}
+
+// an integer compared to a timestamp counts in the unit of the timestamp
+fn timestamp_nanos(v: &ColumnValue, other: &ColumnValue) -> Option<i128> {
+    match (int_value(v), other) {
+        (Some(i), ColumnValue::Timestamp { unit, .. }) => i64::try_from(i).ok().map(|i| to_nanos(i, *unit)),
+        _ => timestamp_value(v),
+    }
+}
+
+// the time zone of a timestamp column of rel, None for other columns
+pub fn timestamp_zone(rel: &dyn Relation, n: usize) -> Option<String> {
+    match rel.ctype(n) {
+        ColumnType::Timestamp { .. } => rel.timezone(n),
+        _ => None,
+    }
+}
+
+// the ISO-8601 string s as a timestamp, without an offset it is a time
+// in 'zone'. Nanoseconds unless they overflow
+fn zoned_timestamp(s: &str, zone: &str) -> Option<ColumnValue> {
+    let nanos = parse_iso8601_in(s, zone)?;
+    Some(match i64::try_from(nanos) {
+        Ok(v) => ColumnValue::Timestamp { v, unit: TimeUnit::Nanos },
+        Err(_) => ColumnValue::Timestamp { v: nanos.div_euclid(1000) as i64, unit: TimeUnit::Micros },
+    })
+}
+
+// a string constant compared to a timestamp column with a time zone, as
+// a timestamp in that zone
+fn zoned_literal(rel: &dyn Relation, v: &Value, other: &Value) -> Option<ColumnValue> {
+    match (v, other) {
+        (Value::Val { val: ColumnValue::String { v } }, Value::Ref { col }) => {
+            zoned_timestamp(v, &timestamp_zone(rel, *col)?)
+        },
+        _ => None,
+    }
+}
+
+fn is_decimal(v: &ColumnValue) -> bool {
+    match v {
+        ColumnValue::Decimal { .. } => true,
+        _ => false,
+    }
+}
+
+// unscaled value and scale, strings are parsed so that literals are exact
+fn decimal_value(v: &ColumnValue) -> Option<(i128, u8)> {
+    match v {
+        ColumnValue::Decimal { v, scale } => Some((*v, *scale)),
+        ColumnValue::String { v } => decimal::parse_decimal(v),
+        _ => int_value(v).map(|i| (i, 0)),
+    }
+}
+
+// the values are read with value_ref, a relation may still have the
+// strings of the row in the file while it is filtered
+fn value_ref<'a>(rel: &'a dyn Relation, v: &'a Value) -> ColumnValueRef<'a> {
+    match v {
+        Value::Val { val } => ColumnValueRef::Value { v: val },
+        Value::Ref { col } => rel.value_ref(*col),
+    }
+}
+
+fn ref_str<'a>(r: &ColumnValueRef<'a>) -> Option<&'a str> {
+    match *r {
+        ColumnValueRef::String { v } => Some(v),
+        ColumnValueRef::Value { v: ColumnValue::String { v } } => Some(v),
+        _ => None,
+    }
+}
+
+// a string that is still in the file is copied
+fn ref_value<'a>(r: ColumnValueRef<'a>) -> Cow<'a, ColumnValue> {
+    match r {
+        ColumnValueRef::String { v } => Cow::Owned(ColumnValue::String { v: v.to_owned() }),
+        ColumnValueRef::Value { v } => Cow::Borrowed(v),
+    }
+}
+
+// like compare, two strings are compared without copying them out of
+// the relation
+fn compare_values(rel: &dyn Relation, l: &Value, r: &Value) -> Option<Ordering> {
+    // resolve_timestamps may have done this already
+    let (lz, rz) = (zoned_literal(rel, l, r), zoned_literal(rel, r, l));
+    let l = match lz {
+        Some(ref v) => ColumnValueRef::Value { v },
+        None => value_ref(rel, l),
     };
-    let rv = match r {
-        Value::Val { val } => val,
-        Value::Ref { col } => rel.value(*col),
+    let r = match rz {
+        Some(ref v) => ColumnValueRef::Value { v },
+        None => value_ref(rel, r),
     };
-    match lv {
-        ColumnValue::Null => {
-            match rv {
-                ColumnValue::Null => isnull, // depends on isnull arg
-                _ => false, // null comparison always yields false
+    match (l, r) {
+        (ColumnValueRef::Value { v: ColumnValue::Null }, _) | (_, ColumnValueRef::Value { v: ColumnValue::Null }) => None,
+        _ => match (ref_str(&l), ref_str(&r)) {
+            (Some(u), Some(v)) => Some(u.cmp(v)),
+            _ => compare(&ref_value(l), &ref_value(r)),
+        },
+    }
+}
+
+// None if the values can not be compared (null, different kinds of values)
+pub fn compare(lv: &ColumnValue, rv: &ColumnValue) -> Option<Ordering> {
+    match (lv, rv) {
+        (ColumnValue::Null, _) | (_, ColumnValue::Null) => None,
+        _ if is_timestamp(lv) || is_timestamp(rv) => {
+            match (timestamp_nanos(lv, rv), timestamp_nanos(rv, lv)) {
+                (Some(u), Some(v)) => Some(u.cmp(&v)),
+                _ => None, // not a valid date
             }
         }
-        ColumnValue::U32 { v } => {
-            let u = v;
-            match rv {
-                ColumnValue::U32 { v } => *u == *v,
-                ColumnValue::U64 { v } => (*u as u64) == *v,
-                _ => false, // string/null comparison
+        _ if is_float(lv) || is_float(rv) => {
+            match (float_value(lv), float_value(rv)) {
+                // NaN only matches NaN so that 'x == NaN' finds NaN rows
+                (Some(u), Some(v)) if u.is_nan() && v.is_nan() => Some(Ordering::Equal),
+                (Some(u), Some(v)) => u.partial_cmp(&v),
+                _ => None, // string comparison
             }
         }
-        ColumnValue::U64 { v } => {
-            let u = v;
-            match rv {
-                ColumnValue::U32 { v } => *u == (*v as u64),
-                ColumnValue::U64 { v } => *u == *v,
-                _ => false, // string/null comparison
+        _ if is_decimal(lv) || is_decimal(rv) => {
+            match (decimal_value(lv), decimal_value(rv)) {
+                (Some((u, us)), Some((v, vs))) => {
+                    let scale = max(us, vs);
+                    match (decimal::rescale(u, us, scale), decimal::rescale(v, vs, scale)) {
+                        (Some(u), Some(v)) => Some(u.cmp(&v)),
+                        _ => None, // overflow
+                    }
+                }
+                _ => None, // not a number
             }
         }
-        ColumnValue::String { v } => {
-            let u = v;
-            match rv {
-                ColumnValue::String { v } => *u == *v,
-                _ => false, // null/u32/u64 comparison
+        (ColumnValue::String { v: u }, ColumnValue::String { v }) => Some(u.cmp(v)),
+        (ColumnValue::Bool { v: u }, ColumnValue::Bool { v }) => Some(u.cmp(v)),
+        (ColumnValue::Bytes { v: u }, ColumnValue::Bytes { v }) => Some(u.cmp(v)),
+        (ColumnValue::List { v: u }, ColumnValue::List { v }) => {
+            // element by element, then the shorter list first
+            for (a, b) in u.iter().zip(v.iter()) {
+                match compare(a, b)? {
+                    Ordering::Equal => {},
+                    o => return Some(o),
+                }
             }
+            Some(u.len().cmp(&v.len()))
         }
+        _ => {
+            match (int_value(lv), int_value(rv)) {
+                (Some(u), Some(v)) => Some(u.cmp(&v)),
+                _ => None, // string/bool/bytes mixed with another type
+            }
+        }
+    }
+}
+
+fn eq(rel: &Relation, l: &Value, r: &Value, isnull: bool) -> bool {
+    match (value_ref(rel, l), value_ref(rel, r)) {
+        (ColumnValueRef::Value { v: ColumnValue::Null }, ColumnValueRef::Value { v: ColumnValue::Null }) => isnull, // depends on isnull arg
+        _ => compare_values(rel, l, r) == Some(Ordering::Equal), // null comparison always yields false
+    }
+}
+
+// ordering comparison, false whenever either side is null
+fn order(rel: &Relation, l: &Value, r: &Value, accept: fn(Ordering) -> bool) -> bool {
+    match compare_values(rel, l, r) {
+        Some(o) => accept(o),
+        None => false,
     }
 }
 
@@ -126,6 +315,10 @@ pub fn eval(rel: &Relation, e: &Expr) -> bool {
     match e {
         Expr::Equal { l, r } => eq(rel, l, r, false),
         Expr::NotEqual { l, r } => !eq(rel, l, r, false),
+        Expr::Less { l, r } => order(rel, l, r, |o| o == Ordering::Less),
+        Expr::LessEqual { l, r } => order(rel, l, r, |o| o != Ordering::Greater),
+        Expr::Greater { l, r } => order(rel, l, r, |o| o == Ordering::Greater),
+        Expr::GreaterEqual { l, r } => order(rel, l, r, |o| o != Ordering::Less),
         Expr::IsNull { l } => eq(rel, l, &Value::Val { val: ColumnValue::Null }, true),
         Expr::NotNull { l } => !eq(rel, l, &Value::Val { val: ColumnValue::Null }, true),
         Expr::And { l, r } => eval(rel, l) && eval(rel, r),
@@ -133,3 +326,126 @@ pub fn eval(rel: &Relation, e: &Expr) -> bool {
         Expr::Not { l } => !eval(rel, l),
     }
 }
+
+fn map_value(v: &Value, f: &Fn(usize) -> Option<usize>) -> Option<Value> {
+    match v {
+        Value::Ref { col } => f(*col).map(|col| Value::Ref { col }),
+        Value::Val { val } => Some(Value::Val { val: val.clone() }),
+    }
+}
+
+// the same expression over another relation, f maps the column numbers,
+// None if a column is not present there
+pub fn map_columns(e: &Expr, f: &Fn(usize) -> Option<usize>) -> Option<Expr> {
+    Some(match e {
+        Expr::Equal { l, r } => Expr::Equal { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::NotEqual { l, r } => Expr::NotEqual { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::Less { l, r } => Expr::Less { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::LessEqual { l, r } => Expr::LessEqual { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::Greater { l, r } => Expr::Greater { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::GreaterEqual { l, r } => Expr::GreaterEqual { l: map_value(l, f)?, r: map_value(r, f)? },
+        Expr::IsNull { l } => Expr::IsNull { l: map_value(l, f)? },
+        Expr::NotNull { l } => Expr::NotNull { l: map_value(l, f)? },
+        Expr::And { l, r } => Expr::And { l: Box::new(map_columns(l, f)?), r: Box::new(map_columns(r, f)?) },
+        Expr::Or { l, r } => Expr::Or { l: Box::new(map_columns(l, f)?), r: Box::new(map_columns(r, f)?) },
+        Expr::Not { l } => Expr::Not { l: Box::new(map_columns(l, f)?) },
+    })
+}
+
+// a string compared to a timestamp column with a time zone is read in
+// that zone, strings that are not dates are left as they are
+fn resolve_value(v: &Value, other: &Value, zone: &dyn Fn(usize) -> Option<String>) -> Value {
+    if let (Value::Val { val: ColumnValue::String { v: s } }, Value::Ref { col }) = (v, other) {
+        if let Some(val) = zone(*col).and_then(|zone| zoned_timestamp(s, &zone)) {
+            return Value::Val { val };
+        }
+    }
+    v.clone()
+}
+
+// e with the strings compared to zoned timestamp columns made timestamps
+// once instead of for every row by eval, 'zone' gives the zone of a
+// column, see timestamp_zone
+pub fn resolve_timestamps(e: &Expr, zone: &dyn Fn(usize) -> Option<String>) -> Expr {
+    let pair = |l: &Value, r: &Value| (resolve_value(l, r, zone), resolve_value(r, l, zone));
+    match e {
+        Expr::Equal { l, r } => { let (l, r) = pair(l, r); Expr::Equal { l, r } },
+        Expr::NotEqual { l, r } => { let (l, r) = pair(l, r); Expr::NotEqual { l, r } },
+        Expr::Less { l, r } => { let (l, r) = pair(l, r); Expr::Less { l, r } },
+        Expr::LessEqual { l, r } => { let (l, r) = pair(l, r); Expr::LessEqual { l, r } },
+        Expr::Greater { l, r } => { let (l, r) = pair(l, r); Expr::Greater { l, r } },
+        Expr::GreaterEqual { l, r } => { let (l, r) = pair(l, r); Expr::GreaterEqual { l, r } },
+        Expr::IsNull { .. } | Expr::NotNull { .. } => e.clone(),
+        Expr::And { l, r } => Expr::And { l: Box::new(resolve_timestamps(l, zone)), r: Box::new(resolve_timestamps(r, zone)) },
+        Expr::Or { l, r } => Expr::Or { l: Box::new(resolve_timestamps(l, zone)), r: Box::new(resolve_timestamps(r, zone)) },
+        Expr::Not { l } => Expr::Not { l: Box::new(resolve_timestamps(l, zone)) },
+    }
+}
+
+// the columns referenced by e
+pub fn columns(e: &Expr) -> Vec<usize> {
+    let mut cols = Vec::new();
+    add_columns(e, &mut cols);
+    cols
+}
+
+fn add_columns(e: &Expr, cols: &mut Vec<usize>) {
+    match e {
+        Expr::Equal { l, r } | Expr::NotEqual { l, r } |
+        Expr::Less { l, r } | Expr::LessEqual { l, r } |
+        Expr::Greater { l, r } | Expr::GreaterEqual { l, r } => {
+            for v in &[l, r] {
+                if let Value::Ref { col } = v {
+                    cols.push(*col);
+                }
+            }
+        },
+        Expr::IsNull { l } | Expr::NotNull { l } => {
+            if let Value::Ref { col } = l {
+                cols.push(*col);
+            }
+        },
+        Expr::And { l, r } | Expr::Or { l, r } => {
+            add_columns(l, cols);
+            add_columns(r, cols);
+        },
+        Expr::Not { l } => add_columns(l, cols),
+    }
+}
+
+#[test]
+fn test_eval_compare() {
+    use types::TimeUnit;
+    use v2::rel::EmptyRelation;
+
+    let rel = EmptyRelation {};
+    let val = |v: ColumnValue| Value::Val { val: v };
+
+    let e = Expr::Equal { l: val(ColumnValue::I64 { v: 5 }), r: val(ColumnValue::U32 { v: 5 }) };
+    assert!(eval(&rel, &e));
+    let e = Expr::Less { l: val(ColumnValue::I32 { v: -1 }), r: val(ColumnValue::U64 { v: 0 }) };
+    assert!(eval(&rel, &e));
+
+    let nan = ::std::f64::NAN;
+    let e = Expr::Equal { l: val(ColumnValue::F64 { v: nan }), r: val(ColumnValue::F32 { v: ::std::f32::NAN }) };
+    assert!(eval(&rel, &e));
+    let e = Expr::Greater { l: val(ColumnValue::F64 { v: nan }), r: val(ColumnValue::F64 { v: 1.0 }) };
+    assert!(!eval(&rel, &e));
+
+    let ts = ColumnValue::Timestamp { v: 1546300800123, unit: TimeUnit::Millis };
+    let e = Expr::Equal { l: val(ts.clone()), r: val(ColumnValue::String { v: "2019-01-01T00:00:00.123Z".to_string() }) };
+    assert!(eval(&rel, &e));
+    let e = Expr::GreaterEqual { l: val(ts.clone()), r: val(ColumnValue::String { v: "2019-01-01".to_string() }) };
+    assert!(eval(&rel, &e));
+
+    let amount = ColumnValue::Decimal { v: 1050, scale: 2 };
+    let e = Expr::Equal { l: val(amount.clone()), r: val(ColumnValue::String { v: "10.5".to_string() }) };
+    assert!(eval(&rel, &e));
+    let e = Expr::Less { l: val(amount.clone()), r: val(ColumnValue::U32 { v: 11 }) };
+    assert!(eval(&rel, &e));
+    let e = Expr::LessEqual { l: val(amount.clone()), r: val(ColumnValue::Decimal { v: 10499, scale: 3 }) };
+    assert!(!eval(&rel, &e));
+
+    let e = Expr::Less { l: val(ColumnValue::Null), r: val(ColumnValue::U32 { v: 1 }) };
+    assert!(!eval(&rel, &e));
+}
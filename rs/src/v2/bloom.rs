@@ -0,0 +1,134 @@
+use std::hash::Hasher;
+use types::{ColumnType, ColumnValue};
+use v2::ast::{int_value, timestamp_value};
+
+extern crate twox_hash;
+use self::twox_hash::XxHash64;
+
+// about 1% false positives
+const BITS_PER_KEY: usize = 10;
+const HASHES: usize = 7;
+
+// set of values of a column in an index block, may_contain can be true
+// for values that were not added but never false for values that were
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bloom {
+    pub hashes: usize,
+    pub bits: Vec<u8>,
+}
+
+impl Bloom {
+    // a filter for the distinct hashes of the values of a block
+    pub fn from_hashes(hashes: &[u64]) -> Bloom {
+        let mut bloom = Bloom {
+            hashes: HASHES,
+            bits: vec![0; (hashes.len() * BITS_PER_KEY).div_ceil(8)],
+        };
+        for h in hashes {
+            bloom.insert(*h);
+        }
+        bloom
+    }
+
+    fn positions(&self, h: u64) -> Vec<usize> {
+        // double hashing with the two halves of the hash
+        let nbits = self.bits.len() as u64 * 8;
+        let (h1, h2) = (h & 0xffff_ffff, h >> 32);
+        (0..self.hashes as u64)
+            .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % nbits) as usize)
+            .collect()
+    }
+
+    fn insert(&mut self, h: u64) {
+        for p in self.positions(h) {
+            self.bits[p / 8] |= 1 << (p % 8);
+        }
+    }
+
+    pub fn may_contain(&self, h: u64) -> bool {
+        if self.bits.is_empty() {
+            return false; // no values were added
+        }
+        self.positions(h).iter().all(|p| self.bits[p / 8] & (1 << (p % 8)) != 0)
+    }
+}
+
+fn hash(tag: u8, bytes: &[u8]) -> u64 {
+    let mut h = XxHash64::with_seed(0);
+    h.write_u8(tag);
+    h.write(bytes);
+    h.finish()
+}
+
+fn timestamp_hash(nanos: i128) -> u64 {
+    hash(b'T', &nanos.to_le_bytes())
+}
+
+// the hash of a value written to a column, values that compare equal
+// hash the same
+pub fn value_hash(value: &ColumnValue) -> Option<u64> {
+    match value {
+        ColumnValue::String { v } => Some(hash(b's', v.as_bytes())),
+        ColumnValue::Bytes { v } => Some(hash(b'b', v)),
+        ColumnValue::Bool { v } => Some(hash(b'o', &[*v as u8])),
+        ColumnValue::Timestamp { .. } => timestamp_value(value).map(timestamp_hash),
+        _ => int_value(value).map(|v| hash(b'i', &v.to_le_bytes())),
+    }
+}
+
+// the hash of a constant compared to a column of type ctype, None if it
+// can not be looked up, e.g. a float that equals an integer
+pub fn bloom_hash(ctype: &ColumnType, value: &ColumnValue) -> Option<u64> {
+    match (ctype, value) {
+        (ColumnType::String, ColumnValue::String { .. }) |
+        (ColumnType::Bytes, ColumnValue::Bytes { .. }) |
+        (ColumnType::FixedBinary { .. }, ColumnValue::Bytes { .. }) |
+        (ColumnType::Bool, ColumnValue::Bool { .. }) => value_hash(value),
+        (ColumnType::U32le, _) | (ColumnType::U64le, _) |
+        (ColumnType::I32le, _) | (ColumnType::I64le, _) => {
+            int_value(value).and_then(|_| value_hash(value))
+        },
+        // strings are read as dates
+        (ColumnType::Timestamp { .. }, ColumnValue::Timestamp { .. }) |
+        (ColumnType::Timestamp { .. }, ColumnValue::String { .. }) => {
+            timestamp_value(value).map(timestamp_hash)
+        },
+        _ => None,
+    }
+}
+
+// columns of these types can have a filter
+pub fn supports_bloom(ctype: &ColumnType) -> bool {
+    matches!(ctype,
+        ColumnType::String | ColumnType::Bytes | ColumnType::FixedBinary { .. } | ColumnType::Bool |
+        ColumnType::U32le | ColumnType::U64le | ColumnType::I32le | ColumnType::I64le |
+        ColumnType::Timestamp { .. })
+}
+
+#[test]
+fn test_bloom() {
+    let hashes: Vec<u64> = (0..1000u64)
+        .map(|n| value_hash(&ColumnValue::U64 { v: n * 2 }).unwrap())
+        .collect();
+    let bloom = Bloom::from_hashes(&hashes);
+    for h in &hashes {
+        assert!(bloom.may_contain(*h));
+    }
+    // odd numbers were not added
+    let false_positives = (0..1000u64)
+        .filter(|n| bloom.may_contain(bloom_hash(&ColumnType::U64le, &ColumnValue::U64 { v: n * 2 + 1 }).unwrap()))
+        .count();
+    assert!(false_positives < 50);
+
+    // integers of different types are the same value
+    assert!(bloom_hash(&ColumnType::U64le, &ColumnValue::I32 { v: 4 }) ==
+            bloom_hash(&ColumnType::U64le, &ColumnValue::U64 { v: 4 }));
+    assert!(bloom_hash(&ColumnType::U64le, &ColumnValue::F64 { v: 4.0 }).is_none());
+    assert!(bloom_hash(&ColumnType::String, &ColumnValue::U32 { v: 4 }).is_none());
+    assert!(!Bloom::from_hashes(&[]).may_contain(hashes[0]));
+
+    use types::TimeUnit;
+    let ts = ColumnType::Timestamp { unit: TimeUnit::Millis };
+    assert!(bloom_hash(&ts, &ColumnValue::String { v: "2019-01-01T00:00:00.123Z".to_string() }) ==
+            value_hash(&ColumnValue::Timestamp { v: 1546300800123, unit: TimeUnit::Millis }));
+}
@@ -1,7 +1,16 @@
 pub trait ReadBuf {
     fn seek(&mut self, pos: usize) -> usize;
+    // the offset of the byte the next readb() returns, seek(pos()) leaves
+    // the buffer where it is. there is no default, every buffer keeps its
+    // position for seek() anyway
+    fn pos(&self) -> usize;
     fn readb(&mut self) -> u8;
     fn past_eof(&mut self) -> bool;
+    // the next n bytes without copying them, moving past them. None if
+    // the buffer does not hold them in memory or fewer are left
+    fn read_slice(&mut self, _n: usize) -> Option<&[u8]> {
+        None
+    }
 }
 
 pub trait AppendBuf {
@@ -9,3 +18,30 @@ pub trait AppendBuf {
     fn flush(&mut self);
 }
 
+
+impl<T: ReadBuf> ReadBuf for &mut T {
+    fn seek(&mut self, pos: usize) -> usize {
+        (**self).seek(pos)
+    }
+    fn pos(&self) -> usize {
+        (**self).pos()
+    }
+    fn readb(&mut self) -> u8 {
+        (**self).readb()
+    }
+    fn past_eof(&mut self) -> bool {
+        (**self).past_eof()
+    }
+    fn read_slice(&mut self, n: usize) -> Option<&[u8]> {
+        (**self).read_slice(n)
+    }
+}
+
+impl<T: AppendBuf> AppendBuf for &mut T {
+    fn writeb(&mut self, u: u8) {
+        (**self).writeb(u)
+    }
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
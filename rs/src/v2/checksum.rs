@@ -0,0 +1,192 @@
+use v2::buf::{ReadBuf, AppendBuf};
+
+extern crate adler32;
+extern crate crc32c;
+extern crate twox_hash;
+use self::adler32::RollingAdler32;
+use std::hash::Hasher;
+use self::twox_hash::XxHash32;
+
+// checksum written after the rows, or after each group of 8 columns
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Checksum {
+    None,
+    Adler32,
+    Crc32c,
+    XxHash32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumGranularity {
+    Group, // one checksum per group of 8 columns
+    Row,   // one checksum per row, cheaper for wide schemas
+}
+
+enum ChecksumState {
+    None,
+    Adler32(RollingAdler32),
+    Crc32c(u32),
+    XxHash32(XxHash32),
+}
+
+impl ChecksumState {
+    fn new(checksum: Checksum) -> ChecksumState {
+        match checksum {
+            Checksum::None => ChecksumState::None,
+            Checksum::Adler32 => ChecksumState::Adler32(RollingAdler32::from_value(1)),
+            Checksum::Crc32c => ChecksumState::Crc32c(0),
+            Checksum::XxHash32 => ChecksumState::XxHash32(XxHash32::with_seed(0)),
+        }
+    }
+
+    fn update(&mut self, b: u8) {
+        match self {
+            ChecksumState::None => {},
+            ChecksumState::Adler32(a) => a.update(b),
+            ChecksumState::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, &[b]),
+            ChecksumState::XxHash32(x) => x.write_u8(b),
+        }
+    }
+
+    fn update_slice(&mut self, bytes: &[u8]) {
+        match self {
+            ChecksumState::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, bytes),
+            ChecksumState::XxHash32(x) => x.write(bytes),
+            _ => {
+                for b in bytes {
+                    self.update(*b);
+                }
+            },
+        }
+    }
+
+    fn hash(&self) -> Option<u32> {
+        match self {
+            ChecksumState::None => None,
+            ChecksumState::Adler32(a) => Some(a.hash()),
+            ChecksumState::Crc32c(crc) => Some(*crc),
+            ChecksumState::XxHash32(x) => Some(x.finish() as u32),
+        }
+    }
+}
+
+pub struct ReadBufChecksum<'a, T: 'a + ReadBuf> {
+    checksum: Checksum,
+    state: ChecksumState,
+    target: &'a mut T,
+}
+
+pub struct AppendBufChecksum<'a, T: 'a + AppendBuf> {
+    checksum: Checksum,
+    state: ChecksumState,
+    target: &'a mut T,
+}
+
+impl<'a, T: ReadBuf> ReadBuf for ReadBufChecksum<'a, T> {
+    fn seek(&mut self, pos: usize) -> usize {
+        self.target.seek(pos)
+    }
+    fn pos(&self) -> usize {
+        self.target.pos()
+    }
+    fn readb(&mut self) -> u8 {
+        let b = self.target.readb();
+        self.state.update(b);
+        b
+    }
+    fn past_eof(&mut self) -> bool {
+        self.target.past_eof()
+    }
+    fn read_slice(&mut self, n: usize) -> Option<&[u8]> {
+        let bytes = self.target.read_slice(n)?;
+        self.state.update_slice(bytes);
+        Some(bytes)
+    }
+}
+
+impl<'a, T: AppendBuf> AppendBuf for AppendBufChecksum<'a, T> {
+    fn flush(&mut self) {
+        self.target.flush();
+    }
+    fn writeb(&mut self, u: u8) {
+        self.target.writeb(u);
+        self.state.update(u);
+    }
+}
+
+impl<'a, T: ReadBuf> ReadBufChecksum<'a, T> {
+    pub fn new(b: &'a mut T, checksum: Checksum) -> ReadBufChecksum<'a, T> {
+        ReadBufChecksum {
+            checksum,
+            state: ChecksumState::new(checksum),
+            target: b,
+        }
+    }
+    // None for Checksum::None
+    pub fn hash(&self) -> Option<u32> {
+        self.state.hash()
+    }
+    // start a new checksum
+    pub fn reset(&mut self) {
+        self.state = ChecksumState::new(self.checksum);
+    }
+    // the stored checksum is read from the target, so it is not part of the checksum
+    pub fn get_mut(&mut self) -> &mut T {
+        self.target
+    }
+}
+
+impl<'a, T: AppendBuf> AppendBufChecksum<'a, T> {
+    pub fn new(b: &'a mut T, checksum: Checksum) -> AppendBufChecksum<'a, T> {
+        AppendBufChecksum {
+            checksum,
+            state: ChecksumState::new(checksum),
+            target: b,
+        }
+    }
+    pub fn hash(&self) -> Option<u32> {
+        self.state.hash()
+    }
+    pub fn reset(&mut self) {
+        self.state = ChecksumState::new(self.checksum);
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        self.target
+    }
+}
+
+#[test]
+fn test_checksums() {
+    use v2::vecbuf::Vecbuf;
+
+    let data = b"123456789";
+    let mut hashes = Vec::new();
+    for checksum in &[Checksum::Adler32, Checksum::Crc32c, Checksum::XxHash32] {
+        let mut out: Vec<u8> = Vec::new();
+        let whash = {
+            let mut w = AppendBufChecksum::new(&mut out, *checksum);
+            for b in data.iter() {
+                w.writeb(*b);
+            }
+            w.hash().unwrap()
+        };
+        let mut vb = Vecbuf::from_vec(out);
+        let mut r = ReadBufChecksum::new(&mut vb, *checksum);
+        for _ in 0..data.len() {
+            r.readb();
+        }
+        assert!(r.hash() == Some(whash));
+        r.reset();
+        assert!(r.hash() != Some(whash));
+        hashes.push(whash);
+    }
+    // the usual check values
+    assert!(hashes[0] == 0x091e01de);
+    assert!(hashes[1] == 0xe3069283);
+    assert!(hashes[2] == 0x937bad67);
+
+    let mut out: Vec<u8> = Vec::new();
+    let mut w = AppendBufChecksum::new(&mut out, Checksum::None);
+    w.writeb(1);
+    assert!(w.hash().is_none());
+}
@@ -0,0 +1,356 @@
+use std::io::{Error, ErrorKind, Read, Write, Result};
+use std::sync::Mutex;
+use std::cmp::min;
+use v2::buf::ReadBuf;
+use v2::vecbuf::Vecbuf;
+use v2::write2::{read_varint, write_varint};
+
+extern crate lz4;
+extern crate zstd;
+extern crate brotli2;
+
+// compression of string and byte values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    None,
+    Lz4 { level: u32 },
+    Zstd { level: i32 },
+    ZstdDict { level: i32 }, // zstd with the dictionary stored in the file header
+    Brotli { quality: u32 }, // slow to write, for archival of long text
+}
+
+// which codec is used for a value depends on its size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Compression {
+    pub small: Codec,     // values shorter than 'threshold'
+    pub large: Codec,     // values of at least 'threshold' bytes
+    pub threshold: usize,
+    pub min_size: usize,  // shorter values are not compressed at all
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::new()
+    }
+}
+
+impl Compression {
+    // lz4 below 4096 bytes and zstd level 15 above
+    pub fn new() -> Compression {
+        Compression {
+            small: Codec::Lz4 { level: 0 },
+            large: Codec::Zstd { level: 15 },
+            threshold: 4096,
+            min_size: 0,
+        }
+    }
+
+    // the same codec for all values
+    pub fn single(codec: Codec) -> Compression {
+        Compression {
+            small: codec,
+            large: codec,
+            threshold: 0,
+            min_size: 0,
+        }
+    }
+
+    pub fn none() -> Compression {
+        Compression::single(Codec::None)
+    }
+
+    pub fn codec(&self, size: usize) -> Codec {
+        if size < self.min_size {
+            Codec::None
+        } else if size < self.threshold {
+            self.small
+        } else {
+            self.large
+        }
+    }
+}
+
+// rows are written in blocks that are compressed as a whole, a block
+// ends after 'rows' rows or once it has at least 'bytes' bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockOptions {
+    pub rows: usize,
+    pub bytes: usize,
+    pub codec: Codec,
+}
+
+impl Default for BlockOptions {
+    fn default() -> BlockOptions {
+        BlockOptions::new()
+    }
+}
+
+impl BlockOptions {
+    pub fn new() -> BlockOptions {
+        BlockOptions {
+            rows: 4096,
+            bytes: 1 << 20,
+            codec: Codec::Zstd { level: 3 },
+        }
+    }
+}
+
+// options of a writer that are not stored in the file
+#[derive(Clone, Debug)]
+pub struct WriteOptions {
+    pub compression: Compression, // unless overridden by the column
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions::new()
+    }
+}
+
+impl WriteOptions {
+    pub fn new() -> WriteOptions {
+        WriteOptions {
+            compression: Compression::new(),
+        }
+    }
+
+    pub fn compression(mut self, compression: Compression) -> WriteOptions {
+        self.compression = compression;
+        self
+    }
+}
+
+// the zstd dictionary of a file, kept with a compressor and a decompressor
+// that are reused for all the values instead of a stream per value. They
+// are made on first use, the compressor again when the level changes
+pub struct ZstdDictionary {
+    bytes: Vec<u8>,
+    compressor: Mutex<Option<(i32, zstd::bulk::Compressor<'static>)>>,
+    decompressor: Mutex<Option<zstd::bulk::Decompressor<'static>>>,
+}
+
+impl ZstdDictionary {
+    pub fn new(bytes: Vec<u8>) -> ZstdDictionary {
+        ZstdDictionary {
+            bytes,
+            compressor: Mutex::new(None),
+            decompressor: Mutex::new(None),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // the size of the value is written before the compressed block, the
+    // decompressor needs it to size its output
+    fn compress(&self, bytes: &[u8], level: i32) -> Result<Vec<u8>> {
+        let mut compressor = self.compressor.lock().unwrap();
+        if compressor.as_ref().map(|c| c.0) != Some(level) {
+            *compressor = Some((level, zstd::bulk::Compressor::with_dictionary(level, &self.bytes)?));
+        }
+        let mut out = Vec::new();
+        write_varint(&mut out, bytes.len());
+        out.extend(compressor.as_mut().unwrap().1.compress(bytes)?);
+        Ok(out)
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        // a varint has at most 10 bytes
+        let mut head = Vecbuf::from_vec(bytes[..min(bytes.len(), 10)].to_vec());
+        let size = read_varint(&mut head);
+        let pos = head.pos();
+        // a zstd block expands at most 2^15 times, larger sizes are corrupt
+        if head.past_eof() || size > bytes.len() << 15 {
+            return Err(Error::new(ErrorKind::InvalidData, "bad size of a zstd value"));
+        }
+        let mut decompressor = self.decompressor.lock().unwrap();
+        if decompressor.is_none() {
+            *decompressor = Some(zstd::bulk::Decompressor::with_dictionary(&self.bytes)?);
+        }
+        let out = decompressor.as_mut().unwrap().decompress(&bytes[pos..], size)?;
+        if out.len() != size {
+            return Err(Error::new(ErrorKind::InvalidData, "bad size of a zstd value"));
+        }
+        Ok(out)
+    }
+}
+
+impl Clone for ZstdDictionary {
+    fn clone(&self) -> ZstdDictionary {
+        ZstdDictionary::new(self.bytes.clone())
+    }
+}
+
+// a codec is identified by the marker byte written before the compressed
+// value, new codecs are added to CODECS under a marker of their own.
+// 'dictionary' is the zstd dictionary of the file, if it has one
+pub type CompressFn = fn(&[u8], &Codec, Option<&ZstdDictionary>) -> Result<Vec<u8>>;
+pub type DecompressFn = fn(&[u8], Option<&ZstdDictionary>) -> Result<Vec<u8>>;
+
+pub struct CodecEntry {
+    pub marker: u8,
+    pub compress: CompressFn,
+    pub decompress: DecompressFn,
+}
+
+static CODECS: &[CodecEntry] = &[
+    CodecEntry { marker: b'L', compress: lz4_compress, decompress: lz4_decompress },
+    CodecEntry { marker: b'Z', compress: zstd_compress, decompress: zstd_decompress },
+    CodecEntry { marker: b'z', compress: zstd_dict_compress, decompress: zstd_dict_decompress },
+    CodecEntry { marker: b'B', compress: brotli_compress, decompress: brotli_decompress },
+];
+
+// marker written for uncompressed values
+pub const MARKER_NONE: u8 = 0;
+
+pub fn marker(codec: &Codec) -> u8 {
+    match codec {
+        Codec::None => MARKER_NONE,
+        Codec::Lz4 { .. } => b'L',
+        Codec::Zstd { .. } => b'Z',
+        Codec::ZstdDict { .. } => b'z',
+        Codec::Brotli { .. } => b'B',
+    }
+}
+
+pub fn find_codec(marker: u8) -> Option<&'static CodecEntry> {
+    CODECS.iter().find(|c| c.marker == marker)
+}
+
+fn lz4_compress(bytes: &[u8], codec: &Codec, _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let level = match codec {
+        Codec::Lz4 { level } => *level,
+        _ => 0,
+    };
+    let mut co = lz4::EncoderBuilder::new()
+        .checksum(lz4::ContentChecksum::NoChecksum)
+        .block_size(lz4::BlockSize::Default)
+        .block_mode(lz4::BlockMode::Linked)
+        .level(level)
+        .build(Vec::new())?;
+    co.write_all(bytes)?;
+    let (outbuf, fres) = co.finish();
+    fres?;
+    Ok(outbuf)
+}
+
+fn lz4_decompress(bytes: &[u8], _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let mut d = lz4::Decoder::new(bytes)?;
+    let mut dbuf: Vec<u8> = Vec::new();
+    d.read_to_end(&mut dbuf)?;
+    Ok(dbuf)
+}
+
+fn zstd_compress(bytes: &[u8], codec: &Codec, _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let level = match codec {
+        Codec::Zstd { level } => *level,
+        _ => 0,
+    };
+    let mut encoder = zstd::stream::Encoder::new(Vec::new(), level)?;
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn zstd_decompress(bytes: &[u8], _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let mut d = zstd::Decoder::new(bytes)?;
+    let mut dbuf: Vec<u8> = Vec::new();
+    d.read_to_end(&mut dbuf)?;
+    Ok(dbuf)
+}
+
+fn no_dictionary() -> Error {
+    Error::new(ErrorKind::InvalidInput, "file has no zstd dictionary")
+}
+
+fn zstd_dict_compress(bytes: &[u8], codec: &Codec, dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let level = match codec {
+        Codec::ZstdDict { level } => *level,
+        _ => 0,
+    };
+    dictionary.ok_or_else(no_dictionary)?.compress(bytes, level)
+}
+
+fn zstd_dict_decompress(bytes: &[u8], dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    dictionary.ok_or_else(no_dictionary)?.decompress(bytes)
+}
+
+// train a zstd dictionary of at most max_size bytes on sample values,
+// a few thousand samples are needed for a useful dictionary
+pub fn train_zstd_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+
+fn brotli_compress(bytes: &[u8], codec: &Codec, _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let quality = match codec {
+        Codec::Brotli { quality } => *quality,
+        _ => 11,
+    };
+    let mut encoder = brotli2::write::BrotliEncoder::new(Vec::new(), quality);
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn brotli_decompress(bytes: &[u8], _dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>> {
+    let mut d = brotli2::read::BrotliDecoder::new(bytes);
+    let mut dbuf: Vec<u8> = Vec::new();
+    d.read_to_end(&mut dbuf)?;
+    Ok(dbuf)
+}
+
+#[test]
+fn test_codecs() {
+    let text: Vec<u8> = (0..10000).map(|n| b"abcabd"[n % 6]).collect();
+    for codec in &[Codec::Lz4 { level: 0 }, Codec::Lz4 { level: 9 }, Codec::Zstd { level: 1 }, Codec::Zstd { level: 19 },
+                  Codec::Brotli { quality: 1 }, Codec::Brotli { quality: 11 }] {
+        let entry = find_codec(marker(codec)).unwrap();
+        let compressed = (entry.compress)(&text, codec, None).unwrap();
+        assert!(compressed.len() < text.len());
+        assert!((entry.decompress)(&compressed, None).unwrap() == text);
+    }
+    assert!(find_codec(MARKER_NONE).is_none());
+
+    // not a brotli stream
+    let entry = find_codec(b'B').unwrap();
+    assert!((entry.decompress)(b"not brotli at all", None).is_err());
+}
+
+#[test]
+fn test_zstd_dictionary() {
+    let samples: Vec<Vec<u8>> = (0..2000)
+        .map(|n| format!("{{\"user\":{},\"action\":\"click\",\"page\":\"/item/{}\"}}", n, n % 97).into_bytes())
+        .collect();
+    let dictionary = train_zstd_dictionary(&samples, 4096).unwrap();
+    assert!(!dictionary.is_empty() && dictionary.len() <= 4096);
+    let dictionary = ZstdDictionary::new(dictionary);
+
+    let value = &samples[1234];
+    let codec = Codec::ZstdDict { level: 3 };
+    let entry = find_codec(marker(&codec)).unwrap();
+    let with_dict = (entry.compress)(value, &codec, Some(&dictionary)).unwrap();
+    let without = zstd_compress(value, &Codec::Zstd { level: 3 }, None).unwrap();
+    assert!(with_dict.len() < without.len());
+    assert!(with_dict.len() < value.len());
+    assert!((entry.decompress)(&with_dict, Some(&dictionary)).unwrap() == *value);
+
+    // the digested dictionary is reused across values and levels
+    for (n, level) in [(7, 1), (8, 19), (9, 1)].iter() {
+        let codec = Codec::ZstdDict { level: *level };
+        let compressed = (entry.compress)(&samples[*n], &codec, Some(&dictionary)).unwrap();
+        assert!((entry.decompress)(&compressed, Some(&dictionary)).unwrap() == samples[*n]);
+    }
+    assert!((entry.decompress)(b"not zstd", Some(&dictionary)).is_err());
+
+    // the dictionary is needed on both sides
+    assert!((entry.compress)(value, &codec, None).is_err());
+    assert!((entry.decompress)(&with_dict, None).is_err());
+}
+
+#[test]
+fn test_compression_codec() {
+    let c = Compression { min_size: 16, ..Compression::new() };
+    assert!(c.codec(8) == Codec::None);
+    assert!(c.codec(100) == Codec::Lz4 { level: 0 });
+    assert!(c.codec(5000) == Codec::Zstd { level: 15 });
+    assert!(Compression::single(Codec::Zstd { level: 3 }).codec(1) == Codec::Zstd { level: 3 });
+}
@@ -0,0 +1,93 @@
+// largest precision that fits the 128-bit backing integer
+pub const MAX_PRECISION: u8 = 38;
+
+// largest precision stored in 8 bytes instead of 16
+pub const MAX_PRECISION_64: u8 = 18;
+
+fn pow10(n: u8) -> Option<i128> {
+    10i128.checked_pow(n as u32)
+}
+
+// true if the unscaled value has at most 'precision' digits
+pub fn fits(v: i128, precision: u8) -> bool {
+    match pow10(precision) {
+        Some(limit) => v > -limit && v < limit,
+        None => true,
+    }
+}
+
+// change the scale of an unscaled value, fails when digits would be lost
+// or the result overflows
+pub fn rescale(v: i128, from: u8, to: u8) -> Option<i128> {
+    if to >= from {
+        v.checked_mul(pow10(to - from)?)
+    } else {
+        let d = pow10(from - to)?;
+        if v % d != 0 {
+            return None;
+        }
+        Some(v / d)
+    }
+}
+
+pub fn to_f64(v: i128, scale: u8) -> f64 {
+    (v as f64) / 10f64.powi(scale as i32)
+}
+
+// parse '-123.45' into the unscaled value and the scale (-12345, 2)
+pub fn parse_decimal(s: &str) -> Option<(i128, u8)> {
+    let s = s.trim();
+    let (negative, digits) = if let Some(digits) = s.strip_prefix('-') {
+        (true, digits)
+    } else if let Some(digits) = s.strip_prefix('+') {
+        (false, digits)
+    } else {
+        (false, s)
+    };
+
+    let mut v: i128 = 0;
+    let mut scale: u8 = 0;
+    let mut seen_point = false;
+    let mut seen_digit = false;
+    for c in digits.bytes() {
+        if c == b'.' && !seen_point {
+            seen_point = true;
+        } else if c.is_ascii_digit() {
+            v = v.checked_mul(10)?.checked_add((c - b'0') as i128)?;
+            seen_digit = true;
+            if seen_point {
+                scale += 1;
+                if scale > MAX_PRECISION {
+                    return None;
+                }
+            }
+        } else {
+            return None;
+        }
+    }
+    if !seen_digit {
+        return None;
+    }
+    Some((if negative { -v } else { v }, scale))
+}
+
+#[test]
+fn test_parse_decimal() {
+    assert!(parse_decimal("123.45") == Some((12345, 2)));
+    assert!(parse_decimal("-0.5") == Some((-5, 1)));
+    assert!(parse_decimal("+7") == Some((7, 0)));
+    assert!(parse_decimal("10.") == Some((10, 0)));
+    assert!(parse_decimal(".").is_none());
+    assert!(parse_decimal("1.2.3").is_none());
+    assert!(parse_decimal("12a").is_none());
+}
+
+#[test]
+fn test_rescale() {
+    assert!(rescale(12345, 2, 4) == Some(1234500));
+    assert!(rescale(1234500, 4, 2) == Some(12345));
+    assert!(rescale(12345, 2, 1).is_none());
+    assert!(rescale(i128::MAX, 0, 1).is_none());
+    assert!(fits(99999, 5));
+    assert!(!fits(-100000, 5));
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use v2::schema2::{Encoding, Schema2};
+
+// a dictionary is reset once it holds this many entries, which bounds
+// the memory used by readers and writers
+pub const MAX_DICT_ENTRIES: usize = 65536;
+
+// codes stored in the row for a dictionary encoded string column
+pub const DICT_CODE_NEW: usize = 0; // new entry, the string follows
+pub const DICT_CODE_RESET: usize = 1; // clear the dictionary, then a new entry follows
+pub const DICT_CODE_FIRST_INDEX: usize = 2; // index + 2 refers to an existing entry
+
+#[derive(Clone)]
+pub struct StringDict {
+    entries: Vec<String>,
+    index: HashMap<String, usize>, // only used when writing
+}
+
+impl StringDict {
+    fn new() -> StringDict {
+        StringDict {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    // code to write for s, the caller writes s itself unless the code
+    // refers to an existing entry
+    pub fn encode(&mut self, s: &str) -> usize {
+        if let Some(n) = self.index.get(s) {
+            return *n + DICT_CODE_FIRST_INDEX;
+        }
+        let code = if self.entries.is_empty() || self.entries.len() >= MAX_DICT_ENTRIES {
+            // a fresh writer always resets, so appending to a file is safe
+            self.entries.clear();
+            self.index.clear();
+            DICT_CODE_RESET
+        } else {
+            DICT_CODE_NEW
+        };
+        self.index.insert(s.to_string(), self.entries.len());
+        self.entries.push(s.to_string());
+        code
+    }
+
+    // entry for an index code
+    pub fn get(&self, code: usize) -> Option<&String> {
+        if code < DICT_CODE_FIRST_INDEX {
+            return None;
+        }
+        self.entries.get(code - DICT_CODE_FIRST_INDEX)
+    }
+
+    // add a new entry read from the file
+    pub fn push(&mut self, code: usize, s: &str) {
+        if code == DICT_CODE_RESET {
+            self.entries.clear();
+        }
+        self.entries.push(s.to_string());
+    }
+}
+
+// per column dictionaries of one file being read or written
+#[derive(Clone)]
+pub struct Dictionaries {
+    columns: Vec<Option<StringDict>>,
+}
+
+impl Dictionaries {
+    pub fn new(schema: &Schema2) -> Dictionaries {
+        let mut columns = Vec::new();
+        for n in 0..schema.names.len() {
+            if schema.encodings[n] == Encoding::Dictionary {
+                columns.push(Some(StringDict::new()));
+            } else {
+                columns.push(None);
+            }
+        }
+        Dictionaries { columns }
+    }
+
+    pub fn column(&mut self, index: usize) -> &mut StringDict {
+        self.columns[index].as_mut().expect("column is not dictionary encoded")
+    }
+}
+
+#[test]
+fn test_encode() {
+    let mut d = StringDict::new();
+    assert!(d.encode("cz") == DICT_CODE_RESET);
+    assert!(d.encode("de") == DICT_CODE_NEW);
+    assert!(d.encode("cz") == DICT_CODE_FIRST_INDEX);
+    assert!(d.encode("de") == DICT_CODE_FIRST_INDEX + 1);
+    assert!(d.get(DICT_CODE_FIRST_INDEX + 1).unwrap() == "de");
+    assert!(d.get(DICT_CODE_NEW).is_none());
+
+    let mut r = StringDict::new();
+    r.push(DICT_CODE_RESET, "x");
+    r.push(DICT_CODE_NEW, "y");
+    r.push(DICT_CODE_RESET, "z");
+    assert!(r.get(DICT_CODE_FIRST_INDEX).unwrap() == "z");
+    assert!(r.get(DICT_CODE_FIRST_INDEX + 1).is_none());
+}
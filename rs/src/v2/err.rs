@@ -7,7 +7,9 @@ pub enum SchemaReadError {
     UnexpectedEof,
     DecompressionError,
     ChecksumError,
-    BadUtf8
+    BadUtf8,
+    BadDictionaryIndex,
+    NeedsRowReader, // the rows of the file can only be read with a RowReader
 }
 
 impl Error for SchemaReadError {
@@ -18,6 +20,8 @@ impl Error for SchemaReadError {
             SchemaReadError::UnexpectedEof => "Unexpected end of file",
             SchemaReadError::ChecksumError => "Checksum error",
             SchemaReadError::BadUtf8 => "Bad UTF-8 encoding",
+            SchemaReadError::BadDictionaryIndex => "Bad dictionary index",
+            SchemaReadError::NeedsRowReader => "The file has to be read with a RowReader",
         }
     }
 }
@@ -1,5 +1,6 @@
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::cmp::min;
+use std::io::{Read, Seek, SeekFrom, Write};
 use v2::buf::{ReadBuf, AppendBuf};
 
 pub struct ReadFileBuf {
@@ -9,6 +10,8 @@ pub struct ReadFileBuf {
     bpos: usize,
     // number of bytes read into buf
     bsize: usize,
+    // position in the file of the start of buf
+    offset: usize,
     // hit eof during the last read
     eof: bool,
 }
@@ -31,6 +34,7 @@ impl ReadFileBuf {
             eof: eof,
             bpos: 0,
             bsize: bsize,
+            offset: 0,
         }
     }
 
@@ -51,6 +55,7 @@ impl ReadFileBuf {
             }
         };
 
+        self.offset += self.bsize;
         self.bsize = bsize;
         self.eof = eof;
         self.bpos = 0;
@@ -58,8 +63,29 @@ impl ReadFileBuf {
 }
 
 impl ReadBuf for ReadFileBuf {
+    // like MmapBuf the position is at most the size of the file
     fn seek(&mut self, pos: usize) -> usize {
-        panic!("not impl");
+        if pos >= self.offset && pos <= self.offset + self.bsize {
+            self.bpos = pos - self.offset;
+            return pos;
+        }
+        let len = match self.f.metadata() {
+            Ok(md) => md.len() as usize,
+            Err(_) => return self.pos(),
+        };
+        let pos = min(pos, len);
+        if self.f.seek(SeekFrom::Start(pos as u64)).is_err() {
+            return self.pos();
+        }
+        self.offset = pos;
+        self.bsize = 0;
+        self.bpos = 0;
+        self.eof = false;
+        self.refill();
+        pos
+    }
+    fn pos(&self) -> usize {
+        self.offset + self.bpos
     }
     fn readb(&mut self) -> u8 {
         if self.bpos >= self.bsize && !self.eof {
@@ -171,3 +197,36 @@ fn rw_test()
         assert!(rf.past_eof());
     }
 }
+
+#[test]
+fn seek_test()
+{
+    {
+        let f = File::create("/tmp/_seek.dat").unwrap();
+        let mut wf = FileBuf::new(f, 4);
+        for b in 0..10 {
+            wf.writeb(b);
+        }
+    }
+    let f = File::open("/tmp/_seek.dat").unwrap();
+    let mut rf = ReadFileBuf::new(f, 4);
+    // outside of the buffer
+    assert!(rf.seek(7) == 7);
+    assert!(rf.readb() == 7);
+    assert!(rf.pos() == 8);
+    // back to the start
+    assert!(rf.seek(1) == 1);
+    assert!(rf.readb() == 1);
+    // inside the buffer
+    assert!(rf.seek(3) == 3);
+    assert!(rf.readb() == 3 && rf.readb() == 4);
+    assert!(rf.seek(9) == 9);
+    assert!(rf.readb() == 9);
+    assert!(rf.past_eof());
+    // not past the end of the file
+    assert!(rf.seek(100) == 10);
+    assert!(rf.past_eof());
+    assert!(rf.seek(0) == 0);
+    assert!(!rf.past_eof());
+    assert!(rf.readb() == 0);
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use types::{ColumnType, ColumnValue};
+use v2::ast::{compare, Expr, Value};
+use v2::bloom::{Bloom, bloom_hash, value_hash};
+
+// rows per index block when the rows are not written in compressed
+// blocks, also used when appending to a file
+pub const DEFAULT_INDEX_ROWS: usize = 4096;
+
+// longer strings are not kept as min/max, the column has no bounds then
+pub const MAX_STATS_STRING: usize = 64;
+
+// statistics of the values of a column in an index block, min and max
+// are Null when not known or when all values are null
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnStats {
+    pub nulls: usize,
+    pub min: ColumnValue,
+    pub max: ColumnValue,
+    bounded: bool, // false once a value without a useful order was seen
+}
+
+impl ColumnStats {
+    pub fn new(ctype: &ColumnType) -> ColumnStats {
+        let bounded = !matches!(ctype, ColumnType::Bytes | ColumnType::FixedBinary { .. } | ColumnType::List { .. });
+        ColumnStats {
+            nulls: 0,
+            min: ColumnValue::Null,
+            max: ColumnValue::Null,
+            bounded,
+        }
+    }
+
+    pub fn add(&mut self, value: &ColumnValue) {
+        match value {
+            ColumnValue::Null => {
+                self.nulls += 1;
+                return;
+            },
+            ColumnValue::String { v } if v.len() > MAX_STATS_STRING => self.unbound(),
+            _ => {},
+        }
+        if !self.bounded {
+            return;
+        }
+        if self.min == ColumnValue::Null {
+            self.min = value.clone();
+            self.max = value.clone();
+        }
+        // NaN does not compare to other values
+        match (compare(value, &self.min), compare(value, &self.max)) {
+            (Some(lo), Some(hi)) => {
+                if lo == Ordering::Less {
+                    self.min = value.clone();
+                }
+                if hi == Ordering::Greater {
+                    self.max = value.clone();
+                }
+            },
+            _ => self.unbound(),
+        }
+    }
+
+    fn unbound(&mut self) {
+        self.bounded = false;
+        self.min = ColumnValue::Null;
+        self.max = ColumnValue::Null;
+    }
+
+    // stats read from a file
+    pub fn from_parts(nulls: usize, min: ColumnValue, max: ColumnValue) -> ColumnStats {
+        ColumnStats {
+            nulls,
+            bounded: min != ColumnValue::Null,
+            min,
+            max,
+        }
+    }
+}
+
+// a range of rows starting at 'offset' in the file, the next block
+// starts where this one ends
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockIndex {
+    pub offset: usize,
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+    pub blooms: Vec<Option<Bloom>>, // for the columns with Schema2::bloom set
+    hashes: Vec<Option<Vec<u64>>>, // of the values added, until finish
+}
+
+impl BlockIndex {
+    // 'bloom' tells which columns get a Bloom filter
+    pub fn new(offset: usize, types: &[ColumnType], bloom: &[bool]) -> BlockIndex {
+        BlockIndex {
+            offset,
+            rows: 0,
+            columns: types.iter().map(ColumnStats::new).collect(),
+            blooms: types.iter().map(|_| None).collect(),
+            hashes: bloom.iter().map(|b| if *b { Some(Vec::new()) } else { None }).collect(),
+        }
+    }
+
+    // an index block read from a file
+    pub fn from_parts(offset: usize, rows: usize, columns: Vec<ColumnStats>, blooms: Vec<Option<Bloom>>) -> BlockIndex {
+        BlockIndex {
+            offset,
+            rows,
+            columns,
+            hashes: blooms.iter().map(|_| None).collect(),
+            blooms,
+        }
+    }
+
+    pub fn add(&mut self, values: &[ColumnValue]) {
+        self.rows += 1;
+        for (stats, value) in self.columns.iter_mut().zip(values.iter()) {
+            stats.add(value);
+        }
+        for (hashes, value) in self.hashes.iter_mut().zip(values.iter()) {
+            if let Some(ref mut hashes) = *hashes {
+                if let Some(h) = value_hash(value) {
+                    hashes.push(h);
+                }
+            }
+        }
+    }
+
+    // builds the Bloom filters once all rows were added
+    pub fn finish(&mut self) {
+        for (bloom, hashes) in self.blooms.iter_mut().zip(self.hashes.iter_mut()) {
+            if let Some(mut hashes) = hashes.take() {
+                hashes.sort();
+                hashes.dedup();
+                *bloom = Some(Bloom::from_hashes(&hashes));
+            }
+        }
+    }
+
+    // the columns with a Bloom filter in the block
+    pub fn bloom_columns(&self) -> Vec<bool> {
+        self.blooms.iter().map(|b| b.is_some()).collect()
+    }
+
+    // false if no row of the block can satisfy e, 'types' are the
+    // types of the columns
+    pub fn may_match(&self, types: &[ColumnType], e: &Expr) -> bool {
+        match e {
+            Expr::Equal { l, r } => {
+                self.may_compare(l, r, |o| o == Ordering::Equal) && self.may_contain(l, r, types)
+            },
+            Expr::Less { l, r } => self.may_compare(l, r, |o| o == Ordering::Less),
+            Expr::LessEqual { l, r } => self.may_compare(l, r, |o| o != Ordering::Greater),
+            Expr::Greater { l, r } => self.may_compare(l, r, |o| o == Ordering::Greater),
+            Expr::GreaterEqual { l, r } => self.may_compare(l, r, |o| o != Ordering::Less),
+            Expr::IsNull { l: Value::Ref { col } } => self.columns[*col].nulls > 0,
+            Expr::NotNull { l: Value::Ref { col } } => self.columns[*col].nulls < self.rows,
+            Expr::And { l, r } => self.may_match(types, l) && self.may_match(types, r),
+            Expr::Or { l, r } => self.may_match(types, l) || self.may_match(types, r),
+            // not equal matches nulls, not is not worth it
+            _ => true,
+        }
+    }
+
+    // false if the Bloom filter of the column does not have the constant
+    fn may_contain(&self, l: &Value, r: &Value, types: &[ColumnType]) -> bool {
+        let (col, val) = match (l, r) {
+            (Value::Ref { col }, Value::Val { val }) | (Value::Val { val }, Value::Ref { col }) => (*col, val),
+            _ => return true,
+        };
+        match (&self.blooms[col], bloom_hash(&types[col], val)) {
+            (Some(bloom), Some(h)) => bloom.may_contain(h),
+            _ => true,
+        }
+    }
+
+    // 'accept' tells if a column value ordered this way against the
+    // constant satisfies the predicate
+    fn may_compare(&self, l: &Value, r: &Value, accept: fn(Ordering) -> bool) -> bool {
+        let (col, val, flip) = match (l, r) {
+            (Value::Ref { col }, Value::Val { val }) => (*col, val, false),
+            (Value::Val { val }, Value::Ref { col }) => (*col, val, true),
+            _ => return true,
+        };
+        let stats = &self.columns[col];
+        if *val == ColumnValue::Null || stats.nulls == self.rows {
+            return false; // comparisons with null are false
+        }
+        if !stats.bounded {
+            return true;
+        }
+        let (lo, hi) = match (compare(&stats.min, val), compare(&stats.max, val)) {
+            (Some(lo), Some(hi)) => (lo, hi),
+            _ => return true, // e.g. NaN
+        };
+        // orderings of the column values against the constant that can occur
+        let accepts = |o: Ordering| accept(if flip { o.reverse() } else { o });
+        (lo == Ordering::Less && accepts(Ordering::Less)) ||
+            (lo != Ordering::Greater && hi != Ordering::Less && accepts(Ordering::Equal)) ||
+            (hi == Ordering::Greater && accepts(Ordering::Greater))
+    }
+}
+
+// the footer of a file, one entry per block of rows
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileIndex {
+    pub blocks: Vec<BlockIndex>,
+}
+
+impl Default for FileIndex {
+    fn default() -> FileIndex {
+        FileIndex::new()
+    }
+}
+
+impl FileIndex {
+    pub fn new() -> FileIndex {
+        FileIndex {
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.blocks.iter().map(|b| b.rows).sum()
+    }
+}
+
+#[test]
+fn test_may_match() {
+    let types = vec![ColumnType::U64le, ColumnType::String];
+    let mut b = BlockIndex::new(0, &types, &[false, false]);
+    for n in 100..200u64 {
+        b.add(&[ColumnValue::U64 { v: n }, ColumnValue::Null]);
+    }
+    let col = || Value::Ref { col: 0 };
+    let val = |n: u64| Value::Val { val: ColumnValue::U64 { v: n } };
+    assert!(b.columns[0].min == ColumnValue::U64 { v: 100 } && b.columns[0].max == ColumnValue::U64 { v: 199 });
+
+    assert!(b.may_match(&types, &Expr::Equal { l: col(), r: val(150) }));
+    assert!(!b.may_match(&types, &Expr::Equal { l: col(), r: val(99) }));
+    assert!(!b.may_match(&types, &Expr::Equal { l: val(200), r: col() }));
+    assert!(!b.may_match(&types, &Expr::Less { l: col(), r: val(100) }));
+    assert!(b.may_match(&types, &Expr::LessEqual { l: col(), r: val(100) }));
+    assert!(!b.may_match(&types, &Expr::Greater { l: col(), r: val(199) }));
+    assert!(b.may_match(&types, &Expr::Greater { l: val(199), r: col() }));
+    assert!(!b.may_match(&types, &Expr::GreaterEqual { l: val(99), r: col() }));
+    assert!(b.may_match(&types, &Expr::NotEqual { l: col(), r: val(99) }));
+
+    // all null
+    let s = || Value::Ref { col: 1 };
+    assert!(!b.may_match(&types, &Expr::Equal { l: s(), r: Value::Val { val: ColumnValue::String { v: "a".to_string() } } }));
+    assert!(b.may_match(&types, &Expr::IsNull { l: s() }));
+    assert!(!b.may_match(&types, &Expr::NotNull { l: s() }));
+    assert!(!b.may_match(&types, &Expr::IsNull { l: col() }));
+
+    let outside = Expr::Equal { l: col(), r: val(5) };
+    let inside = Expr::Equal { l: col(), r: val(105) };
+    assert!(!b.may_match(&types, &Expr::And { l: Box::new(outside.clone()), r: Box::new(inside.clone()) }));
+    assert!(b.may_match(&types, &Expr::Or { l: Box::new(outside), r: Box::new(inside) }));
+
+    // a long string leaves the column without bounds
+    let mut b = BlockIndex::new(0, &types, &[false, false]);
+    b.add(&[ColumnValue::U64 { v: 1 }, ColumnValue::String { v: "b".to_string() }]);
+    let a = Expr::Equal { l: s(), r: Value::Val { val: ColumnValue::String { v: "a".to_string() } } };
+    assert!(!b.may_match(&types, &a));
+    b.add(&[ColumnValue::U64 { v: 2 }, ColumnValue::String { v: "x".repeat(100) }]);
+    assert!(b.may_match(&types, &a));
+}
@@ -8,39 +8,66 @@ pub struct MmapBuf {
     f: File,
     m: Mmap,
     pos: usize,
+    end: usize, // reads stop here, e.g. at the footer
 }
 
 impl MmapBuf {
     pub fn new(f: File) -> MmapBuf {
-        let mmap = unsafe { Mmap::map(&f) };
+        let mmap = unsafe { Mmap::map(&f) }.unwrap();
+        let end = mmap.len();
         MmapBuf {
-            m: mmap.unwrap(),
+            m: mmap,
             f: f,
             pos: 0,
+            end,
         }
     }
+
+    // size of the file
+    pub fn len(&self) -> usize {
+        self.m.len()
+    }
+
+    // read only the first 'end' bytes of the file
+    pub fn set_end(&mut self, end: usize) {
+        self.end = end;
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    // bytes of the file, e.g. at a position returned by read_slice
+    pub fn bytes(&self, pos: usize, len: usize) -> &[u8] {
+        &self.m[pos..pos + len]
+    }
 }
 
 impl ReadBuf for MmapBuf {
     #[inline]
     fn past_eof(&mut self) -> bool {
         // only report eof once we are PAST it
-        self.pos > self.m.len()
+        self.pos > self.end
     }
 
     #[inline]
     fn seek(&mut self, pos: usize) -> usize {
-        if pos >= self.m.len() {
-            self.pos = self.m.len();
+        if pos >= self.end {
+            self.pos = self.end;
         } else {
             self.pos = pos;
         }
         self.pos
     }
 
+    #[inline]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
     #[inline]
     fn readb(&mut self) -> u8 {
-        if self.pos >= self.m.len() {
+        if self.pos >= self.end {
             // have to advance the pos to make past_eof work
             self.pos += 1;
             0 as u8
@@ -50,4 +77,14 @@ impl ReadBuf for MmapBuf {
             u
         }
     }
+
+    #[inline]
+    fn read_slice(&mut self, n: usize) -> Option<&[u8]> {
+        if n > self.end - self.pos.min(self.end) {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += n;
+        Some(&self.m[start..self.pos])
+    }
 }
@@ -4,7 +4,16 @@ pub mod write2;
 pub mod mmapbuf;
 pub mod filebuf;
 pub mod vecbuf;
-pub mod adlerbuf;
 pub mod rel;
 pub mod ast;
 pub mod err;
+pub mod timestamp;
+pub mod decimal;
+pub mod dict;
+pub mod codec;
+pub mod writer;
+pub mod reader;
+pub mod sync;
+pub mod index;
+pub mod bloom;
+pub mod checksum;
@@ -0,0 +1,469 @@
+use types::ColumnValue;
+use std::mem;
+use v2::buf::ReadBuf;
+use v2::schema2::{Schema, Schema2};
+use v2::dict::Dictionaries;
+use v2::vecbuf::Vecbuf;
+use v2::err::SchemaReadError;
+use v2::write2::{read_row_with, skip_row_with, read_block, read_row_group, read_sync, find_sync};
+
+// reads the rows of a file, keeping the state needed between rows
+pub struct RowReader {
+    dicts: Dictionaries,
+    block: Vecbuf,     // decompressed rows of the current block in block mode
+    block_rows: usize, // rows left in the current block
+    block_start: usize, // position of the current block in the file
+    group: Vec<Vec<ColumnValue>>, // values of the current row group in columnar files
+    needed: Vec<bool>, // columns read from row groups
+    segment_rows: usize, // rows read since the last sync record
+    lost_rows: usize,
+}
+
+impl RowReader {
+    pub fn new(schema: &Schema2) -> RowReader {
+        RowReader {
+            dicts: Dictionaries::new(schema),
+            block: Vecbuf::from_vec(Vec::new()),
+            block_rows: 0,
+            block_start: 0,
+            group: Vec::new(),
+            needed: vec![true; schema.len()],
+            segment_rows: 0,
+            lost_rows: 0,
+        }
+    }
+
+    // rows skipped over by resynchronizing after corrupt data
+    pub fn lost_rows(&self) -> usize {
+        self.lost_rows
+    }
+
+    // in columnar files only the needed columns are read, the values of
+    // the others are Null
+    pub fn set_needed(&mut self, needed: Vec<bool>) {
+        self.needed = needed;
+    }
+
+    // false while rows of a compressed block are left
+    pub fn at_block_start(&self) -> bool {
+        self.block_rows == 0
+    }
+
+    // the caller has moved the buffer past 'rows' rows at the start of a
+    // block, the dictionaries start empty there
+    pub fn skip(&mut self, schema: &Schema2, rows: usize) {
+        self.dicts = Dictionaries::new(schema);
+        self.block_rows = 0;
+        self.segment_rows += rows;
+    }
+
+    // the caller has moved the buffer to the start of a row, or of a block
+    // in block mode, where the dictionaries were 'dicts'
+    pub fn restart(&mut self, dicts: Dictionaries) {
+        self.dicts = dicts;
+        self.block_rows = 0;
+        self.segment_rows = 0;
+    }
+
+    // in files with sync records the reader continues after the next
+    // record when a row cannot be read, the error is still returned
+    pub fn read_row<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        values: &mut [ColumnValue],
+        schema: &Schema2,
+    ) -> Result<(), SchemaReadError> {
+        self.read_row_with(buf, values, None, schema)
+    }
+
+    // like read_row, the uncompressed strings of files without blocks are
+    // not copied, see read_row_with
+    pub fn read_row_spans<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        values: &mut [ColumnValue],
+        spans: &mut [Option<(usize, usize)>],
+        schema: &Schema2,
+    ) -> Result<(), SchemaReadError> {
+        self.read_row_with(buf, values, Some(spans), schema)
+    }
+
+    // moves past a row like read_row without making its values, errors
+    // are handled as in read_row
+    pub fn skip_row<B: ReadBuf>(&mut self, buf: &mut B, schema: &Schema2) -> Result<(), SchemaReadError> {
+        self.read_synced(buf, schema, |r, buf| r.skip_next(buf, schema).map(|_| 1)).map(|_| ())
+    }
+
+    // columnar files only: the values of the current row group that were
+    // not read yet, or of the next group, are moved into 'group' in row
+    // order, at most 'max' rows. Returns the number of rows, columns that
+    // are not needed are left empty. Errors are handled as in read_row
+    pub fn read_group<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        group: &mut Vec<Vec<ColumnValue>>,
+        max: usize,
+        schema: &Schema2,
+    ) -> Result<usize, SchemaReadError> {
+        self.read_synced(buf, schema, |r, buf| r.read_next_group(buf, group, max, schema))
+    }
+
+    fn read_row_with<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        values: &mut [ColumnValue],
+        mut spans: Option<&mut [Option<(usize, usize)>]>,
+        schema: &Schema2,
+    ) -> Result<(), SchemaReadError> {
+        self.read_synced(buf, schema, |r, buf| {
+            r.read_next(buf, values, spans.as_deref_mut(), schema).map(|_| 1)
+        }).map(|_| ())
+    }
+
+    // 'read' reads rows and returns how many
+    fn read_synced<B, F>(&mut self, buf: &mut B, schema: &Schema2, mut read: F) -> Result<usize, SchemaReadError>
+        where B: ReadBuf, F: FnMut(&mut RowReader, &mut B) -> Result<usize, SchemaReadError>
+    {
+        let sync = match schema.sync {
+            Some(sync) => sync,
+            None => return read(self, buf),
+        };
+
+        if self.block_rows == 0 {
+            while read_sync(buf, &sync).is_some() {
+                self.segment_rows = 0;
+            }
+        }
+        let row_start = buf.pos();
+        match read(self, buf) {
+            Ok(rows) => {
+                self.segment_rows += rows;
+                Ok(rows)
+            },
+            Err(SchemaReadError::Eof) => Err(SchemaReadError::Eof),
+            Err(e) => {
+                // a length may be corrupt, so search from just after the
+                // start of the row or block that failed
+                let start = if schema.blocks.is_some() { self.block_start } else { row_start };
+                self.block_rows = 0;
+                if let Some(nrows) = find_sync(buf, &sync, start + 1) {
+                    self.lost_rows += nrows.saturating_sub(self.segment_rows);
+                    self.segment_rows = 0;
+                    self.dicts = Dictionaries::new(schema);
+                }
+                Err(e)
+            },
+        }
+    }
+
+    fn read_next<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        values: &mut [ColumnValue],
+        spans: Option<&mut [Option<(usize, usize)>]>,
+        schema: &Schema2,
+    ) -> Result<(), SchemaReadError> {
+        if schema.blocks.is_none() {
+            return read_row_with(buf, values, spans, schema, &mut self.dicts);
+        }
+
+        if schema.columnar {
+            return self.read_group_row(buf, values, schema);
+        }
+        self.load_block(buf, schema)?;
+        self.block_rows -= 1;
+        let r = read_row_with(&mut self.block, values, None, schema, &mut self.dicts);
+        self.block_row_read(r)
+    }
+
+    fn skip_next<B: ReadBuf>(&mut self, buf: &mut B, schema: &Schema2) -> Result<(), SchemaReadError> {
+        if schema.blocks.is_none() {
+            return skip_row_with(buf, schema, &mut self.dicts);
+        }
+
+        if schema.columnar {
+            self.load_group(buf, schema)?;
+            self.block_rows -= 1;
+            for column in self.group.iter_mut() {
+                column.pop();
+            }
+            return Ok(());
+        }
+        self.load_block(buf, schema)?;
+        self.block_rows -= 1;
+        let r = skip_row_with(&mut self.block, schema, &mut self.dicts);
+        self.block_row_read(r)
+    }
+
+    // reads the next block when the rows of the current one are used up
+    fn load_block<B: ReadBuf>(&mut self, buf: &mut B, schema: &Schema2) -> Result<(), SchemaReadError> {
+        while self.block_rows == 0 {
+            self.block_start = buf.pos();
+            let (nrows, rows) = read_block(buf, schema.prepared_zstd_dictionary())?;
+            self.block = Vecbuf::from_vec(rows);
+            self.block_rows = nrows;
+        }
+        Ok(())
+    }
+
+    fn block_row_read(&mut self, r: Result<(), SchemaReadError>) -> Result<(), SchemaReadError> {
+        match r {
+            // the block has fewer rows than it claims
+            Err(SchemaReadError::Eof) => {
+                self.block_rows = 0;
+                Err(SchemaReadError::UnexpectedEof)
+            },
+            r => r,
+        }
+    }
+
+    fn read_group_row<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        values: &mut [ColumnValue],
+        schema: &Schema2,
+    ) -> Result<(), SchemaReadError> {
+        self.load_group(buf, schema)?;
+        self.block_rows -= 1;
+        for (value, column) in values.iter_mut().zip(self.group.iter_mut()) {
+            *value = column.pop().unwrap_or(ColumnValue::Null);
+        }
+        Ok(())
+    }
+
+    // reads the next row group when the rows of the current one are used up
+    fn load_group<B: ReadBuf>(&mut self, buf: &mut B, schema: &Schema2) -> Result<(), SchemaReadError> {
+        while self.block_rows == 0 {
+            self.block_start = buf.pos();
+            self.block_rows = read_row_group(buf, schema, &self.needed, &mut self.group)?;
+            // so that the values of the next row are at the end
+            for column in self.group.iter_mut() {
+                column.reverse();
+            }
+        }
+        Ok(())
+    }
+
+    fn read_next_group<B: ReadBuf>(
+        &mut self,
+        buf: &mut B,
+        group: &mut Vec<Vec<ColumnValue>>,
+        max: usize,
+        schema: &Schema2,
+    ) -> Result<usize, SchemaReadError> {
+        let rows = if self.block_rows > 0 {
+            // the rest of a group that was read row by row
+            for column in self.group.iter_mut() {
+                column.reverse();
+            }
+            mem::swap(&mut self.group, group);
+            self.block_rows
+        } else {
+            self.block_start = buf.pos();
+            read_row_group(buf, schema, &self.needed, group)?
+        };
+        self.block_rows = 0;
+        if rows > max {
+            // the other rows are left for the next read, at the end of
+            // the columns as read_group_row expects
+            self.group = group.iter_mut().map(|column| {
+                let mut rest = if column.len() > max { column.split_off(max) } else { Vec::new() };
+                rest.reverse();
+                rest
+            }).collect();
+            self.block_rows = rows - max;
+            return Ok(max);
+        }
+        Ok(rows)
+    }
+}
+
+#[test]
+fn test_blocks() {
+    use types::ColumnType;
+    use v2::codec::{BlockOptions, Codec, Compression, WriteOptions};
+    use v2::write2::{read_schema_v2, write_schema_v2, schema_write};
+    use v2::writer::Writer;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+
+    let rows: Vec<Vec<ColumnValue>> = (0..1000u64).map(|n| vec![
+        ColumnValue::U64 { v: n },
+        if n % 10 == 0 { ColumnValue::Null } else { ColumnValue::String { v: format!("/usr/share/doc/{}", n % 13) } },
+    ]).collect();
+
+    // without blocks, for comparison
+    let mut plain: Vec<u8> = Vec::new();
+    write_schema_v2(&mut plain, &sch);
+    for row in &rows {
+        assert!(schema_write(&mut plain, row.as_slice(), &sch));
+    }
+
+    for codec in &[Codec::Lz4 { level: 0 }, Codec::Zstd { level: 3 }] {
+        sch.set_blocks(Some(BlockOptions { rows: 300, bytes: 1 << 20, codec: *codec }));
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let options = WriteOptions::new().compression(Compression::none());
+            let mut w = Writer::create(&mut out, &sch, options);
+            for row in &rows {
+                assert!(w.write(row.as_slice()));
+            }
+            // a value that does not match is rejected and not written
+            assert!(!w.write(&[ColumnValue::Null, ColumnValue::Null]));
+        }
+        assert!(out.len() < plain.len() / 2);
+
+        let mut vb = Vecbuf::from_vec(out);
+        let rsch = read_schema_v2(&mut vb).unwrap();
+        assert!(rsch.blocks.is_some());
+        let mut reader = RowReader::new(&rsch);
+        let mut rrow = vec![ColumnValue::Null; 2];
+        for row in &rows {
+            assert!(reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch).is_ok());
+            assert!(*row == rrow);
+        }
+        match reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch) {
+            Err(SchemaReadError::Eof) => {},
+            _ => panic!("expected Eof"),
+        }
+    }
+}
+
+#[test]
+fn test_sync() {
+    use types::ColumnType;
+    use v2::codec::{BlockOptions, Codec, Compression, WriteOptions};
+    use v2::write2::read_schema_v2;
+    use v2::writer::Writer;
+    use v2::sync::SyncOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+    let sync = SyncOptions::new(100);
+    sch.set_sync(Some(sync));
+
+    let rows: Vec<Vec<ColumnValue>> = (0..1000u64).map(|n| vec![
+        ColumnValue::U64 { v: n },
+        if n % 10 == 0 { ColumnValue::Null } else { ColumnValue::String { v: format!("/var/log/{}", n % 7) } },
+    ]).collect();
+
+    for blocks in &[None, Some(BlockOptions { rows: 50, bytes: 1 << 20, codec: Codec::Zstd { level: 3 } })] {
+        sch.set_blocks(*blocks);
+        let mut out: Vec<u8> = Vec::new();
+        {
+            let options = WriteOptions::new().compression(Compression::none());
+            let mut w = Writer::create(&mut out, &sch, options);
+            for row in &rows {
+                assert!(w.write(row.as_slice()));
+            }
+        }
+
+        // the marker is in the header too, the third record is before row 300
+        let records: Vec<usize> = (0..out.len() - sync.marker.len())
+            .filter(|p| out[*p..*p + sync.marker.len()] == sync.marker)
+            .skip(1)
+            .collect();
+        assert!(records.len() == 10);
+        let record_len = sync.marker.len() + 8 + 1;
+        let p = records[2] + record_len;
+        if blocks.is_none() {
+            // row 300 has a null path, in row 301 the length of the path
+            // is damaged so that it runs past the end of the file
+            out[p + 13 + 10] = 0xff;
+            out[p + 13 + 11] = 0xff;
+        } else {
+            // in the compressed rows of the first block
+            out[p + 10] ^= 0x55;
+        }
+
+        let mut vb = Vecbuf::from_vec(out);
+        let rsch = read_schema_v2(&mut vb).unwrap();
+        assert!(rsch.sync.unwrap().marker == sync.marker);
+        let mut reader = RowReader::new(&rsch);
+        let mut rrow = vec![ColumnValue::Null; 2];
+        let mut ids = Vec::new();
+        let mut errors = 0;
+        loop {
+            match reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch) {
+                Ok(()) => {
+                    let id = match rrow[0] { ColumnValue::U64 { v } => v, _ => panic!("bad id") };
+                    assert!(rrow == rows[id as usize]);
+                    ids.push(id);
+                },
+                Err(SchemaReadError::Eof) => break,
+                Err(_) => errors += 1,
+            }
+        }
+        assert!(errors == 1);
+        let first_lost = if blocks.is_none() { 301 } else { 300 };
+        let expected: Vec<u64> = (0..1000).filter(|n| *n < first_lost || *n >= 400).collect();
+        assert!(ids == expected);
+        assert!(reader.lost_rows() == 400 - first_lost as usize);
+    }
+}
+
+#[test]
+fn test_columnar() {
+    use types::ColumnType;
+    use v2::codec::{BlockOptions, Codec, Compression, WriteOptions};
+    use v2::schema2::Encoding;
+    use v2::write2::read_schema_v2;
+    use v2::writer::Writer;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+    sch.add("ok", ColumnType::Bool, true);
+    sch.add("score", ColumnType::F64le, false);
+    sch.add("tags", ColumnType::List { inner: Box::new(ColumnType::String) }, true);
+    sch.set_encoding(0, Encoding::Varint);
+    sch.set_dictionary(1, true);
+    sch.set_blocks(Some(BlockOptions { rows: 300, bytes: 1 << 20, codec: Codec::Zstd { level: 3 } }));
+    sch.set_columnar(true);
+
+    let rows: Vec<Vec<ColumnValue>> = (0..1000u64).map(|n| vec![
+        ColumnValue::U64 { v: n },
+        if n % 10 == 0 { ColumnValue::Null } else { ColumnValue::String { v: format!("/usr/lib/{}", n % 13) } },
+        if n % 7 == 0 { ColumnValue::Null } else { ColumnValue::Bool { v: n % 3 == 0 } },
+        ColumnValue::F64 { v: n as f64 / 4.0 },
+        if n % 2 == 0 { ColumnValue::Null } else { ColumnValue::List { v: vec![ColumnValue::String { v: format!("t{}", n % 5) }] } },
+    ]).collect();
+
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let options = WriteOptions::new().compression(Compression::none());
+        let mut w = Writer::create(&mut out, &sch, options);
+        for row in &rows {
+            assert!(w.write(row.as_slice()));
+        }
+        assert!(!w.write(&[ColumnValue::Null, ColumnValue::Null, ColumnValue::Null, ColumnValue::Null, ColumnValue::Null]));
+    }
+
+    let mut vb = Vecbuf::from_vec(out.clone());
+    let rsch = read_schema_v2(&mut vb).unwrap();
+    assert!(rsch.columnar && rsch.blocks.is_some());
+    let mut reader = RowReader::new(&rsch);
+    let mut rrow = vec![ColumnValue::Null; 5];
+    for row in &rows {
+        assert!(reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch).is_ok());
+        assert!(*row == rrow);
+    }
+    match reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch) {
+        Err(SchemaReadError::Eof) => {},
+        _ => panic!("expected Eof"),
+    }
+
+    // only two of the columns
+    let mut vb = Vecbuf::from_vec(out);
+    let rsch = read_schema_v2(&mut vb).unwrap();
+    let mut reader = RowReader::new(&rsch);
+    reader.set_needed(vec![true, false, false, true, false]);
+    for row in &rows {
+        assert!(reader.read_row(&mut vb, rrow.as_mut_slice(), &rsch).is_ok());
+        assert!(rrow[0] == row[0] && rrow[3] == row[3]);
+        assert!(rrow[1] == ColumnValue::Null && rrow[2] == ColumnValue::Null && rrow[4] == ColumnValue::Null);
+    }
+}
@@ -1,17 +1,26 @@
-use types::{ColumnValue, ColumnType, Relation};
-use v2::schema2::{Schema, Schema2};
+use types::{Batch, ColumnValue, ColumnValueRef, ColumnType, Relation};
+use v2::schema2::{Encoding, Schema, Schema2};
 use v2::mmapbuf::MmapBuf;
-use v2::write2::{read_schema_v2, schema_read_row};
-use v2::ast::{Expr, eval, Value, parse_expr};
+use v2::buf::ReadBuf;
+use v2::write2::{read_schema_v2, read_footer, read_sync, rfind_sync};
+use v2::reader::RowReader;
+use v2::index::{FileIndex, DEFAULT_INDEX_ROWS};
+use v2::dict::Dictionaries;
+use v2::ast::{Expr, eval, columns, map_columns, resolve_timestamps, timestamp_zone, Value, parse_expr};
 use v2::err::{SchemaReadError};
+use v2::timestamp::to_nanos;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{File, read_dir};
 use std::io::{Error, ErrorKind, Result};
 //use std::rc::Rc;
 //use std::cell::RefCell;
 use std::borrow::Borrow;
 use std::borrow::BorrowMut;
+use std::cell::OnceCell;
+use std::mem;
+use std::slice;
+use std::str;
 
 extern crate regex;
 use self::regex::Regex;
@@ -19,6 +28,24 @@ extern crate tiny_keccak;
 use self::tiny_keccak::Shake;
 use v2::rel::tiny_keccak::Hasher;
 
+// fill 'batch' with the columns 'map' of the 'rows' rows of 'from',
+// columns mapped to None are null. The vectors are moved unless a
+// column is used again
+fn map_batch(from: &mut Batch, batch: &mut Batch, map: &[Option<usize>], rows: usize) {
+    batch.reset(map.len());
+    for (n, m) in map.iter().enumerate() {
+        match m {
+            Some(m) if map[n + 1..].contains(&Some(*m)) => batch.columns[n].clone_from(&from.columns[*m]),
+            Some(m) => mem::swap(&mut batch.columns[n], &mut from.columns[*m]),
+            None => {
+                for _ in 0..rows {
+                    batch.columns[n].push(ColumnValue::Null);
+                }
+            },
+        }
+    }
+}
+
 pub struct EmptyRelation {
 }
 
@@ -50,9 +77,26 @@ impl Relation for EmptyRelation {
 pub struct FileRelation {
     schema: Schema2,
     m: MmapBuf,
+    reader: RowReader,
     current: Vec<ColumnValue>,
+    spans: Vec<Option<(usize, usize)>>, // strings of the current row left in the file, checked utf-8
+    strings: Vec<OnceCell<ColumnValue>>, // the strings of spans, copied once value() asks for them
+    group: Vec<Vec<ColumnValue>>, // moved from the reader into batches in columnar files
     done: bool,
     name: String, // used for printing errors
+    index: Option<FileIndex>, // from the footer
+    next_block: usize, // first index block not yet reached
+    filters: Vec<Expr>, // blocks are skipped unless they may satisfy all of these
+    skipped_rows: usize,
+    needed: Option<Vec<bool>>, // columns passed to require, all if None
+    row: usize, // number of the row read next
+    checkpoints: Vec<Checkpoint>, // sparse row offset index for seek_row
+}
+
+// a row where reading can start with empty dictionaries
+struct Checkpoint {
+    row: usize,
+    pos: usize,
 }
 
 impl Relation for FileRelation {
@@ -60,39 +104,7 @@ impl Relation for FileRelation {
         self.schema.len()
     }
     fn read(&mut self) -> bool {
-
-        loop {
-            let result = schema_read_row(
-                &mut self.m,
-                self.current.as_mut_slice(),
-                &self.schema
-            );
-
-            match result {
-                Ok(_) => return true, // have more data
-                Err(e) => {
-                    match e {
-                        SchemaReadError::UnexpectedEof => {
-                            println!("SchemaReadError::UnexpectedEof");
-                            return false;
-                        },
-                        SchemaReadError::Eof => return false,
-                        SchemaReadError::ChecksumError => {
-                            println!("SchemaReadError::ChecksumError");
-                            // continue to next row
-                        },
-                        SchemaReadError::BadUtf8 => {
-                            println!("SchemaReadError::BadUtf8 {}", self.name);
-                            // continue to next row
-                        },
-                        SchemaReadError::DecompressionError => {
-                            println!("SchemaReadError::DecompressionError {}", self.name);
-                            // continue to next row
-                        },
-                    }
-                }
-            }
-        }
+        self.next_row(true)
     }
     fn name(&self, n: usize) -> String {
         self.schema.name(n).to_owned()
@@ -103,9 +115,61 @@ impl Relation for FileRelation {
     fn nullable(&self, n: usize) -> bool {
         self.schema.nullable(n)
     }
+    fn description(&self, n: usize) -> Option<String> {
+        self.schema.description(n).map(|d| d.to_owned())
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        self.schema.timezone(n).map(|tz| tz.to_owned())
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        self.schema.metadata.clone()
+    }
+    // a string left in the file is copied on the first call
     fn value(&self, n: usize) -> &ColumnValue {
         assert!(self.done == false);
-        self.current[n].borrow()
+        match self.spans[n] {
+            Some(span) => self.strings[n].get_or_init(|| ColumnValue::String { v: self.string(span).to_owned() }),
+            None => self.current[n].borrow(),
+        }
+    }
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        assert!(self.done == false);
+        match self.spans[n] {
+            Some(span) => ColumnValueRef::String { v: self.string(span) },
+            None => ColumnValueRef::from(&self.current[n]),
+        }
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        batch.reset(self.schema.len());
+        if self.schema.columnar && self.schema.blocks.is_some() {
+            return self.read_groups(batch, max);
+        }
+        let mut rows = 0;
+        while rows < max && self.next_row(true) {
+            for n in 0..self.current.len() {
+                let value = match self.spans[n].take() {
+                    Some(span) => ColumnValue::String { v: self.string(span).to_owned() },
+                    None => mem::replace(&mut self.current[n], ColumnValue::Null),
+                };
+                batch.columns[n].push(value);
+            }
+            rows += 1;
+        }
+        rows
+    }
+    // the index blocks that can not match are skipped, the rows of the
+    // others filtered on the strings left in the file
+    fn restrict(&mut self, e: &Expr) {
+        let e = resolve_timestamps(e, &|n| timestamp_zone(self, n));
+        self.filters.push(e);
+    }
+    fn require(&mut self, columns: &[usize]) {
+        let len = self.schema.len();
+        let needed = self.needed.get_or_insert_with(|| vec![false; len]);
+        for col in columns {
+            needed[*col] = true;
+        }
+        self.reader.set_needed(needed.clone());
     }
     fn dump_debug_info(&self) {
         println!("==== FileRelation");
@@ -138,50 +202,495 @@ impl FileRelation {
 
         let mut mmapbuf = MmapBuf::new(f);
 
-        let sch = read_schema_v2(&mut mmapbuf).unwrap();
+        let sch = match read_schema_v2(&mut mmapbuf) {
+            Some(sch) => sch,
+            None => {
+                let bad_header = Error::new(ErrorKind::InvalidData, "not a flatfile, or written by a newer version");
+                return Err(bad_header);
+            }
+        };
+        let start = mmapbuf.pos();
+        let mut index = None;
+        if sch.index_rows.is_some() {
+            let len = mmapbuf.len();
+            if let Some((footer, end)) = read_footer(&mut mmapbuf, &sch, len) {
+                index = Some(footer);
+                mmapbuf.set_end(end);
+            }
+            mmapbuf.seek(start);
+        }
 
         for _ in 0..sch.len() {
             readvec.push(ColumnValue::Null);
         }
 
+        // the dictionaries start empty at every block of the index
+        let mut checkpoints = vec![Checkpoint { row: 0, pos: start }];
+        if let Some(ref index) = index {
+            let mut row = 0;
+            for block in &index.blocks {
+                if block.offset > start {
+                    checkpoints.push(Checkpoint { row, pos: block.offset });
+                }
+                row += block.rows;
+            }
+        }
+
+        let reader = RowReader::new(&sch);
         let r = FileRelation {
+            spans: vec![None; sch.len()],
+            strings: (0..sch.len()).map(|_| OnceCell::new()).collect(),
+            group: Vec::new(),
             schema: sch,
             m: mmapbuf,
+            reader,
             current: readvec,
             done: false,
-            name: fname.to_owned()
+            name: fname.to_owned(),
+            index,
+            next_block: 0,
+            filters: Vec::new(),
+            skipped_rows: 0,
+            needed: None,
+            row: 0,
+            checkpoints,
         };
 
         Ok(r)
     }
+
+    // 'filtered' skips the blocks that can not satisfy the filters
+    fn next_row(&mut self, filtered: bool) -> bool {
+        loop {
+            if filtered {
+                self.skip_blocks();
+            }
+            self.add_checkpoint();
+            let lost_before = self.reader.lost_rows();
+            let filters = filtered && !self.filters.is_empty();
+            let result = self.read_current(true);
+
+            match result {
+                Ok(_) => {
+                    self.row += 1;
+                    if filters && !self.filters.iter().all(|e| eval(self, e)) {
+                        continue;
+                    }
+                    return true; // have more data
+                },
+                Err(e) => {
+                    if !self.read_error(e, lost_before) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    // the row groups of a columnar file are moved into the batch whole,
+    // then filtered
+    fn read_groups(&mut self, batch: &mut Batch, max: usize) -> usize {
+        let mut rows = 0;
+        while rows < max {
+            self.skip_blocks();
+            self.add_checkpoint();
+            let lost_before = self.reader.lost_rows();
+            match self.reader.read_group(&mut self.m, &mut self.group, max - rows, &self.schema) {
+                Ok(n) => {
+                    self.row += n;
+                    for (column, values) in batch.columns.iter_mut().zip(self.group.iter_mut()) {
+                        // the columns that are not needed are not read
+                        values.resize(n, ColumnValue::Null);
+                        column.append(values);
+                    }
+                    if !self.filters.is_empty() {
+                        let keep = batch_matches(self, batch, &self.filters, rows);
+                        batch.retain(&keep);
+                    }
+                    rows = batch.rows();
+                },
+                Err(e) => {
+                    if !self.read_error(e, lost_before) {
+                        break;
+                    }
+                }
+            }
+        }
+        rows
+    }
+
+    // reports an error of the reader, false at the end of the file
+    fn read_error(&mut self, e: SchemaReadError, lost_before: usize) -> bool {
+        let lost = self.reader.lost_rows() - lost_before;
+        self.row += lost;
+        if lost > 0 {
+            println!("skipped to the next sync marker, {} rows lost {}", lost, self.name);
+        }
+        match e {
+            SchemaReadError::UnexpectedEof => {
+                println!("SchemaReadError::UnexpectedEof");
+                return false;
+            },
+            SchemaReadError::Eof => return false,
+            SchemaReadError::ChecksumError => {
+                println!("SchemaReadError::ChecksumError");
+                // continue to next row
+            },
+            SchemaReadError::BadUtf8 => {
+                println!("SchemaReadError::BadUtf8 {}", self.name);
+                // continue to next row
+            },
+            SchemaReadError::DecompressionError => {
+                println!("SchemaReadError::DecompressionError {}", self.name);
+                // continue to next row
+            },
+            SchemaReadError::BadDictionaryIndex => {
+                println!("SchemaReadError::BadDictionaryIndex {}", self.name);
+                // continue to next row
+            },
+            SchemaReadError::NeedsRowReader => {
+                // not returned by the RowReader
+                println!("SchemaReadError::NeedsRowReader {}", self.name);
+                return false;
+            },
+        }
+        // the row was read, only its values are not used
+        self.row += 1;
+        true
+    }
+
+    // read the next row into current. With 'spans' the strings that can
+    // be are left in the file and are Null in current, value_ref borrows
+    // them from the file and value copies them
+    fn read_current(&mut self, spans: bool) -> ::std::result::Result<(), SchemaReadError> {
+        for s in self.strings.iter_mut() {
+            s.take();
+        }
+        if spans {
+            self.reader.read_row_spans(&mut self.m, self.current.as_mut_slice(), self.spans.as_mut_slice(), &self.schema)
+        } else {
+            for span in self.spans.iter_mut() {
+                *span = None;
+            }
+            self.reader.read_row(&mut self.m, self.current.as_mut_slice(), &self.schema)
+        }
+    }
+
+    // a string of the current row left in the file
+    fn string(&self, span: (usize, usize)) -> &str {
+        // the row was only read if the bytes of its spans are utf-8
+        unsafe { str::from_utf8_unchecked(self.m.bytes(span.0, span.1)) }
+    }
+
+    // number of the row that read returns next, only exact in files
+    // without corrupt data
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    // move to row n so that read returns it next, false if the file has
+    // fewer rows. Starts from the nearest row in the index of the footer,
+    // or of the rows seen so far in files without one. Dictionary encoded
+    // files without an index or sync records have no place to start other
+    // than the first row, so seeking back in them reads from the start
+    pub fn seek_row(&mut self, n: usize) -> bool {
+        let cp = self.checkpoints.iter().rposition(|cp| cp.row <= n).unwrap_or(0);
+        if n < self.row || self.row < self.checkpoints[cp].row {
+            let cp = &self.checkpoints[cp];
+            self.m.seek(cp.pos);
+            self.reader.restart(Dictionaries::new(&self.schema));
+            self.row = cp.row;
+            self.next_block = 0;
+        }
+        // the rows in between are moved over, not read
+        while self.row < n {
+            self.add_checkpoint();
+            let lost_before = self.reader.lost_rows();
+            match self.reader.skip_row(&mut self.m, &self.schema) {
+                Ok(_) => self.row += 1,
+                Err(e) => {
+                    if !self.read_error(e, lost_before) {
+                        return false;
+                    }
+                },
+            }
+        }
+        true
+    }
+
+    // move past the next n rows without returning them
+    pub fn skip(&mut self, n: usize) -> bool {
+        let row = self.row + n;
+        self.seek_row(row)
+    }
+
+    // the start of the last range of rows that ends at 'end' and the
+    // dictionaries there, None at the start of the file. The ranges are
+    // only known in files with an index or sync records
+    fn segment_before(&mut self, end: usize) -> Option<(usize, Dictionaries)> {
+        let start = self.checkpoints[0].pos;
+        if end <= start {
+            return None;
+        }
+        if let Some(ref index) = self.index {
+            if let Some(block) = index.blocks.iter().rev().find(|b| b.offset < end) {
+                return Some((block.offset, Dictionaries::new(&self.schema)));
+            }
+        } else if let Some(sync) = self.schema.sync {
+            if let Some(pos) = rfind_sync(&mut self.m, &sync, start, end) {
+                return Some((pos, Dictionaries::new(&self.schema)));
+            }
+        }
+        Some((start, Dictionaries::new(&self.schema)))
+    }
+
+    // the rows from 'start' to 'end', rows that can not be read are left out
+    fn read_segment(&mut self, start: usize, dicts: Dictionaries, end: usize) -> Vec<Vec<ColumnValue>> {
+        self.m.seek(start);
+        self.reader.restart(dicts);
+        let mut rows = Vec::new();
+        let len = self.current.len();
+        while !(self.reader.at_block_start() && self.m.pos() >= end) {
+            match self.read_current(false) {
+                Ok(_) => rows.push(mem::replace(&mut self.current, vec![ColumnValue::Null; len])),
+                Err(SchemaReadError::Eof) | Err(SchemaReadError::UnexpectedEof) => break,
+                Err(_) => {},
+            }
+        }
+        rows
+    }
+
+    // remember where the rows start while reading files without an index.
+    // a row can only be a start at the beginning of a block, and in files
+    // with dictionaries only where the writer emptied them, at a sync record.
+    // Dictionary encoded files without sync records get none past row 0
+    fn add_checkpoint(&mut self) {
+        if self.index.is_some() || !self.reader.at_block_start() {
+            return;
+        }
+        let last = self.checkpoints[self.checkpoints.len() - 1].row;
+        if self.row < last + DEFAULT_INDEX_ROWS {
+            return;
+        }
+        let pos = self.m.pos();
+        if self.schema.encodings.contains(&Encoding::Dictionary) {
+            let at_sync = match self.schema.sync {
+                Some(sync) => read_sync(&mut self.m, &sync).is_some(),
+                None => false,
+            };
+            self.m.seek(pos);
+            if !at_sync {
+                return;
+            }
+        }
+        self.checkpoints.push(Checkpoint { row: self.row, pos });
+    }
+
+    // rows skipped over after corrupt data, only known for files with sync markers
+    pub fn lost_rows(&self) -> usize {
+        self.reader.lost_rows()
+    }
+
+    // the index from the footer, if the file has one
+    pub fn index(&self) -> Option<&FileIndex> {
+        self.index.as_ref()
+    }
+
+    // number of rows in the file, without reading them
+    pub fn row_count(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.rows())
+    }
+
+    // rows not read because the index showed that they do not match
+    pub fn skipped_rows(&self) -> usize {
+        self.skipped_rows
+    }
+
+    // moves past the index blocks whose rows can not satisfy the filters
+    fn skip_blocks(&mut self) {
+        let types = &self.schema.types;
+        let index = match self.index {
+            Some(ref index) => index,
+            None => return,
+        };
+        if !self.reader.at_block_start() {
+            return;
+        }
+        let mut pos = self.m.pos();
+        while self.next_block < index.blocks.len() && index.blocks[self.next_block].offset < pos {
+            self.next_block += 1;
+        }
+        while self.next_block < index.blocks.len() && index.blocks[self.next_block].offset == pos {
+            let block = &index.blocks[self.next_block];
+            self.next_block += 1;
+            if self.filters.iter().all(|e| block.may_match(types, e)) {
+                break;
+            }
+            // the last block ends where the footer starts
+            let next = index.blocks.get(self.next_block).map(|b| b.offset).unwrap_or(usize::max_value());
+            pos = self.m.seek(next);
+            self.reader.skip(&self.schema, block.rows);
+            self.skipped_rows += block.rows;
+            self.row += block.rows;
+        }
+    }
+}
+
+// the rows of a file from the last to the first. The file is read in
+// ranges from the end, which are found with the index in the footer or
+// the sync records
+pub struct ReverseRelation {
+    file: FileRelation,
+    rows: Vec<Vec<ColumnValue>>, // rows of the current range, the next is last
+    current: Vec<ColumnValue>,
+    end: usize, // start of the range read last
+}
+
+impl ReverseRelation {
+    // fails for files with neither an index nor sync records, they can
+    // only be read from the start
+    pub fn new(file: FileRelation) -> Result<ReverseRelation> {
+        if file.index.is_none() && file.schema.sync.is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput, "file has neither an index nor sync records"));
+        }
+        let end = file.m.end();
+        Ok(ReverseRelation {
+            current: vec![ColumnValue::Null; file.length()],
+            file,
+            rows: Vec::new(),
+            end,
+        })
+    }
+}
+
+impl Relation for ReverseRelation {
+    fn length(&self) -> usize {
+        self.file.length()
+    }
+    fn read(&mut self) -> bool {
+        loop {
+            if let Some(row) = self.rows.pop() {
+                self.current = row;
+                return true;
+            }
+            match self.file.segment_before(self.end) {
+                Some((start, dicts)) => {
+                    self.rows = self.file.read_segment(start, dicts, self.end);
+                    self.end = start;
+                },
+                None => return false,
+            }
+        }
+    }
+    fn name(&self, n: usize) -> String {
+        self.file.name(n)
+    }
+    fn ctype(&self, n: usize) -> ColumnType {
+        self.file.ctype(n)
+    }
+    fn nullable(&self, n: usize) -> bool {
+        self.file.nullable(n)
+    }
+    fn description(&self, n: usize) -> Option<String> {
+        self.file.description(n)
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        self.file.timezone(n)
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        self.file.metadata()
+    }
+    fn value(&self, n: usize) -> &ColumnValue {
+        &self.current[n]
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        batch.reset(self.current.len());
+        let mut rows = 0;
+        while rows < max && self.read() {
+            for (column, value) in batch.columns.iter_mut().zip(self.current.iter_mut()) {
+                column.push(mem::replace(value, ColumnValue::Null));
+            }
+            rows += 1;
+        }
+        rows
+    }
+    fn restrict(&mut self, _e: &Expr) {
+        // the ranges are read in full
+    }
+    fn require(&mut self, columns: &[usize]) {
+        self.file.require(columns);
+    }
+    fn dump_debug_info(&self) {
+        println!("==== ReverseRelation");
+        self.file.dump_debug_info();
+    }
+}
+
+// a row of a batch read from 'rel', for evaluating expressions on it
+struct BatchRow<'a> {
+    rel: &'a dyn Relation,
+    batch: &'a Batch,
+    row: usize,
+}
+
+impl<'a> Relation for BatchRow<'a> {
+    fn length(&self) -> usize {
+        self.rel.length()
+    }
+    fn read(&mut self) -> bool {
+        false
+    }
+    fn name(&self, n: usize) -> String {
+        self.rel.name(n)
+    }
+    fn ctype(&self, n: usize) -> ColumnType {
+        self.rel.ctype(n)
+    }
+    fn nullable(&self, n: usize) -> bool {
+        self.rel.nullable(n)
+    }
+    fn value(&self, n: usize) -> &ColumnValue {
+        self.batch.value(self.row, n)
+    }
+    fn dump_debug_info(&self) {
+        println!("==== BatchRow");
+        println!("  .row={}", self.row);
+    }
+}
+
+// which rows of 'batch' satisfy all of 'filters', the rows before
+// 'start' are kept without evaluating them
+fn batch_matches(rel: &dyn Relation, batch: &Batch, filters: &[Expr], start: usize) -> Vec<bool> {
+    (0..batch.rows())
+        .map(|row| row < start || filters.iter().all(|e| eval(&BatchRow { rel, batch, row }, e)))
+        .collect()
 }
 
 pub struct Restriction {
     rel: Box<Relation>,
     e:   Expr,
+    batch: Batch, // read from 'rel' to be filtered
 }
 
 impl Restriction {
-    pub fn new(base: Box<Relation>,
+    pub fn new(mut base: Box<Relation>,
                e: Expr,
     ) -> Restriction {
+        let e = resolve_timestamps(&e, &|n| timestamp_zone(base.borrow(), n));
+        base.restrict(&e);
         Restriction {
             rel: base,
             e: e,
+            batch: Batch::new(),
         }
     }
 }
 
 impl Relation for Restriction {
     fn read(&mut self) -> bool {
-        let mut found = false;
-        while !found {
-            let done = self.rel.read();
-            if done {
-                return done;
-            }
+        while self.rel.read() {
             if eval(self.rel.borrow(), &self.e) {
-                found = true;
+                return true;
             }
         }
         false
@@ -198,9 +707,44 @@ impl Relation for Restriction {
     fn nullable(&self, n: usize) -> bool {
         self.rel.nullable(n)
     }
+    fn description(&self, n: usize) -> Option<String> {
+        self.rel.description(n)
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        self.rel.timezone(n)
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        self.rel.metadata()
+    }
     fn value(&self, n: usize) -> &ColumnValue {
         self.rel.value(n)
     }
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        self.rel.value_ref(n)
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        batch.reset(self.rel.length());
+        let mut rows = 0;
+        while rows < max {
+            if self.rel.read_batch(&mut self.batch, max - rows) == 0 {
+                break;
+            }
+            let keep = batch_matches(self.rel.borrow(), &self.batch, slice::from_ref(&self.e), 0);
+            rows += keep.iter().filter(|k| **k).count();
+            self.batch.retain(&keep);
+            batch.append(&mut self.batch);
+        }
+        rows
+    }
+    fn restrict(&mut self, e: &Expr) {
+        self.rel.restrict(e);
+    }
+    fn require(&mut self, cols: &[usize]) {
+        // the columns of the expression are read to evaluate it
+        let mut cols = cols.to_vec();
+        cols.extend(columns(&self.e));
+        self.rel.require(&cols);
+    }
     fn dump_debug_info(&self) {
         println!("==== Restriction");
     }
@@ -211,6 +755,73 @@ pub struct UniqueRelation {
     columns: Vec<usize>, // indices of columns to be made unique
     hsize: usize,
     hashset: Vec<u128>,
+    batch: Batch, // read from 'relation' to remove the rows seen before
+}
+
+fn hash_value(k: &mut Shake, value: &ColumnValue) {
+    match value {
+        ColumnValue::U32 { v } => {
+            let b: [u8; 4] = [
+                ((v & 0xff) as u8),
+                ((v >> 8) as u8) & 0xff,
+                ((v >> 16) as u8) & 0xff,
+                ((v >> 24) as u8) & 0xff
+            ];
+            k.update(&b);
+        },
+        ColumnValue::U64 { v } => {
+            let b: [u8; 8] = [
+                ((v & 0xff) as u8),
+                ((v >> 8) as u8) & 0xff,
+                ((v >> 16) as u8) & 0xff,
+                ((v >> 24) as u8) & 0xff,
+                ((v >> 32) as u8) & 0xff,
+                ((v >> 40) as u8) & 0xff,
+                ((v >> 48) as u8) & 0xff,
+                ((v >> 56) as u8) & 0xff
+            ];
+            k.update(&b);
+        },
+        ColumnValue::I32 { v } => {
+            k.update(&v.to_le_bytes());
+        },
+        ColumnValue::I64 { v } => {
+            k.update(&v.to_le_bytes());
+        },
+        ColumnValue::F32 { v } => {
+            k.update(&v.to_bits().to_le_bytes());
+        },
+        ColumnValue::F64 { v } => {
+            k.update(&v.to_bits().to_le_bytes());
+        },
+        ColumnValue::Bool { v } => {
+            k.update(&[*v as u8]);
+        },
+        ColumnValue::Timestamp { v, unit } => {
+            k.update(&to_nanos(*v, *unit).to_le_bytes());
+        },
+        ColumnValue::Decimal { v, scale } => {
+            k.update(&v.to_le_bytes());
+            k.update(&[*scale]);
+        },
+        ColumnValue::String { v } => {
+            k.update(v.as_bytes());
+        },
+        ColumnValue::Bytes { v } => {
+            k.update(v);
+        },
+        ColumnValue::List { v } => {
+            k.update(&(v.len() as u64).to_le_bytes());
+            for e in v {
+                hash_value(k, e);
+                // element delimiter
+                k.update(&[0u8]);
+            }
+        },
+        ColumnValue::Null => {
+            k.update(&[0u8]);
+        }
+    }
 }
 
 impl UniqueRelation {
@@ -245,43 +856,16 @@ impl UniqueRelation {
             relation: rel,
             hashset: hashset,
             hsize: hsize,
+            batch: Batch::new(),
         }
     }
 
-    fn seen(&mut self) -> bool {
+    // the hash of the 'columns' of a row, read with 'value'
+    fn hash<'a, F: Fn(usize) -> &'a ColumnValue>(columns: &[usize], value: F) -> u128 {
         let mut k = Shake::v128();
         let nil = vec![0u8];
-        for j in &self.columns {
-            match self.relation.value(*j) {
-                ColumnValue::U32 { v } => {
-                    let b: [u8; 4] = [
-                        ((v & 0xff) as u8),
-                        ((v >> 8) as u8) & 0xff,
-                        ((v >> 16) as u8) & 0xff,
-                        ((v >> 24) as u8) & 0xff
-                    ];
-                    k.update(&b);
-                },
-                ColumnValue::U64 { v } => {
-                    let b: [u8; 8] = [
-                        ((v & 0xff) as u8),
-                        ((v >> 8) as u8) & 0xff,
-                        ((v >> 16) as u8) & 0xff,
-                        ((v >> 24) as u8) & 0xff,
-                        ((v >> 32) as u8) & 0xff,
-                        ((v >> 40) as u8) & 0xff,
-                        ((v >> 48) as u8) & 0xff,
-                        ((v >> 56) as u8) & 0xff
-                    ];
-                    k.update(&b);
-                },
-                ColumnValue::String { v } => {
-                    k.update(v.as_bytes());
-                },
-                ColumnValue::Null => {
-                    k.update(&nil);
-                }
-            }
+        for j in columns {
+            hash_value(&mut k, value(*j));
             // column delimiter
             k.update(&nil);
         }
@@ -304,7 +888,10 @@ impl UniqueRelation {
             ((res[13] as u128) << 13*8) +
             ((res[14] as u128) << 14*8) +
             ((res[15] as u128) << 15*8);
+        hash
+    }
 
+    fn seen(&mut self, hash: u128) -> bool {
         let mut pos = (hash % (self.hsize as u128)) as usize;
         while self.hashset[pos] != 0 {
             if self.hashset[pos] == hash {
@@ -330,7 +917,8 @@ impl Relation for UniqueRelation {
         loop {
             let r = self.relation.read();
             if (r) {
-                if !self.seen() {
+                let hash = UniqueRelation::hash(&self.columns, |n| self.relation.value(n));
+                if !self.seen(hash) {
                     return true;
                 }
             } else {
@@ -347,9 +935,47 @@ impl Relation for UniqueRelation {
     fn nullable(&self, n: usize) -> bool {
         self.relation.nullable(n)
     }
+    fn description(&self, n: usize) -> Option<String> {
+        self.relation.description(n)
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        self.relation.timezone(n)
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        self.relation.metadata()
+    }
     fn value(&self, n: usize) -> &ColumnValue {
         self.relation.value(n)
     }
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        self.relation.value_ref(n)
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        batch.reset(self.relation.length());
+        let mut rows = 0;
+        while rows < max {
+            let n = self.relation.read_batch(&mut self.batch, max - rows);
+            if n == 0 {
+                break;
+            }
+            let hashes: Vec<u128> = (0..n)
+                .map(|row| UniqueRelation::hash(&self.columns, |col| self.batch.value(row, col)))
+                .collect();
+            let keep: Vec<bool> = hashes.into_iter().map(|hash| !self.seen(hash)).collect();
+            rows += keep.iter().filter(|k| **k).count();
+            self.batch.retain(&keep);
+            batch.append(&mut self.batch);
+        }
+        rows
+    }
+    fn restrict(&mut self, _e: &Expr) {
+        // the rows are remembered to find duplicates, so all are read
+    }
+    fn require(&mut self, columns: &[usize]) {
+        let mut columns = columns.to_vec();
+        columns.extend(self.columns.iter().filter(|c| **c != usize::max_value()));
+        self.relation.require(&columns);
+    }
     fn dump_debug_info(&self) {
         println!("==== Unique");
         println!("  .columns={:?}", self.columns);
@@ -362,6 +988,7 @@ pub struct Projection {
     relation: Box<Relation>,
     colmap: Vec<usize>,
     colcount: usize,
+    batch: Batch, // rows of relation for read_batch
 }
 
 impl<'a> Projection {
@@ -385,11 +1012,15 @@ impl<'a> Projection {
             }
         }
 
-        Projection {
+        let mut p = Projection {
             relation: rel,
             colmap: colmap,
             colcount: cols.len(),
-        }
+            batch: Batch::new(),
+        };
+        let all: Vec<usize> = (0..p.colmap.len()).collect();
+        p.require(&all);
+        p
     }
 }
 
@@ -412,10 +1043,46 @@ impl Relation for Projection {
         let m = self.colmap[n];
         self.relation.nullable(m)
     }
+    fn description(&self, n: usize) -> Option<String> {
+        let m = self.colmap[n];
+        self.relation.description(m)
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        let m = self.colmap[n];
+        self.relation.timezone(m)
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        self.relation.metadata()
+    }
     fn value(&self, n: usize) -> &ColumnValue {
         let m = self.colmap[n];
         self.relation.value(m)
     }
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        let m = self.colmap[n];
+        self.relation.value_ref(m)
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        let rows = self.relation.read_batch(&mut self.batch, max);
+        let map: Vec<Option<usize>> = self.colmap.iter()
+            .map(|m| Some(*m).filter(|m| *m != usize::max_value()))
+            .collect();
+        map_batch(&mut self.batch, batch, &map, rows);
+        rows
+    }
+    fn restrict(&mut self, e: &Expr) {
+        let colmap = &self.colmap;
+        if let Some(e) = map_columns(e, &|n| colmap.get(n).cloned().filter(|m| *m != usize::max_value())) {
+            self.relation.restrict(&e);
+        }
+    }
+    fn require(&mut self, columns: &[usize]) {
+        let columns: Vec<usize> = columns.iter()
+            .map(|n| self.colmap[*n])
+            .filter(|m| *m != usize::max_value())
+            .collect();
+        self.relation.require(&columns);
+    }
     fn dump_debug_info(&self) {
         println!("==== Projection");
     }
@@ -426,6 +1093,7 @@ pub struct ConcatRelation {
     current: usize,
     schema: Schema2,
     mapping: Vec<isize>,
+    batch: Batch, // rows of the current relation for read_batch
 }
 
 impl ConcatRelation {
@@ -437,6 +1105,7 @@ impl ConcatRelation {
             // union of all columns
             schema: Schema2::new(),
             mapping: Vec::new(),
+            batch: Batch::new(),
         }
     }
     pub fn size(&self) -> usize {
@@ -458,6 +1127,15 @@ impl ConcatRelation {
         }
         assert!(self.mapping.len() == self.schema.len());
     }
+    // continue with the next relation after the current one was read
+    fn next_relation(&mut self) {
+        self.current += 1;
+        if self.current < self.relations.len() {
+            self.reindex();
+        } else {
+            self.mapping.clear();
+        }
+    }
     pub fn add(&mut self, rel: Box<Relation>) -> bool {
         // first check that the schema is the same
         if self.relations.len() > 0 {
@@ -471,6 +1149,12 @@ impl ConcatRelation {
                 }
 
                 if let Some(index) = found {
+                    if self.schema.description(index).is_none() {
+                        if let Some(d) = rel.description(i) {
+                            self.schema.set_description(index, Some(&d));
+                        }
+                    }
+
                     if self.schema.ctype(index) != rel.ctype(i) {
                         println!("union: types of {} are different: {:?} and {:?}",
                                  rel.name(i), rel.ctype(i), self.schema.ctype(index));
@@ -493,6 +1177,9 @@ impl ConcatRelation {
                         println!("union: new column {} - is nullable",
                                  rel.name(i));
                         self.schema.add(&rel.name(i), rel.ctype(i), rel.nullable(i));
+                        let index = self.schema.len() - 1;
+                        self.schema.set_description(index, rel.description(i).as_ref().map(|d| d.as_str()));
+                        self.schema.set_timezone(index, rel.timezone(i).as_deref());
                     } else {
                         println!("union: ERROR: new column {} - NOT NULLABLE",
                                  rel.name(i));
@@ -508,6 +1195,14 @@ impl ConcatRelation {
                     rel.ctype(i),
                     rel.nullable(i)
                 );
+                self.schema.set_description(i, rel.description(i).as_ref().map(|d| d.as_str()));
+                self.schema.set_timezone(i, rel.timezone(i).as_deref());
+            }
+        }
+        // the first relation wins when the metadata keys clash
+        for (key, value) in rel.metadata() {
+            if self.schema.get_metadata(&key).is_none() {
+                self.schema.set_metadata(&key, &value);
             }
         }
         self.relations.push(rel);
@@ -528,12 +1223,7 @@ impl Relation for ConcatRelation {
             if self.current < self.relations.len() {
                 let ok = self.relations[self.current].read();
                 if !ok {
-                    self.current += 1;
-                    if self.current < self.relations.len() {
-                        self.reindex();
-                    } else {
-                        self.mapping.clear();
-                    }
+                    self.next_relation();
                 } else {
                     return ok;
                 }
@@ -555,6 +1245,18 @@ impl Relation for ConcatRelation {
         assert!(self.current < self.relations.len());
         self.schema.nullable(n)
     }
+    fn description(&self, n: usize) -> Option<String> {
+        assert!(self.current < self.relations.len());
+        self.schema.description(n).map(|d| d.to_owned())
+    }
+    fn timezone(&self, n: usize) -> Option<String> {
+        assert!(self.current < self.relations.len());
+        self.schema.timezone(n).map(|tz| tz.to_owned())
+    }
+    fn metadata(&self) -> BTreeMap<String, String> {
+        assert!(self.current < self.relations.len());
+        self.schema.metadata.clone()
+    }
     fn value(&self, n: usize) -> &ColumnValue {
         assert!(self.current < self.relations.len());
         let m = self.mapping[n];
@@ -565,6 +1267,52 @@ impl Relation for ConcatRelation {
             &ColumnValue::Null
         }
     }
+    fn value_ref(&self, n: usize) -> ColumnValueRef<'_> {
+        assert!(self.current < self.relations.len());
+        let m = self.mapping[n];
+        if m != -1 {
+            self.relations[self.current].value_ref(m as usize)
+        } else {
+            ColumnValueRef::Value { v: &ColumnValue::Null }
+        }
+    }
+    fn read_batch(&mut self, batch: &mut Batch, max: usize) -> usize {
+        while self.current < self.relations.len() {
+            let rows = self.relations[self.current].read_batch(&mut self.batch, max);
+            if rows > 0 {
+                let map: Vec<Option<usize>> = self.mapping.iter()
+                    .map(|m| if *m != -1 { Some(*m as usize) } else { None })
+                    .collect();
+                map_batch(&mut self.batch, batch, &map, rows);
+                return rows;
+            }
+            self.next_relation();
+        }
+        batch.reset(self.schema.len());
+        0
+    }
+    fn restrict(&mut self, e: &Expr) {
+        // columns are matched by name, a relation without one of the
+        // columns is not restricted
+        for rel in self.relations.iter_mut() {
+            let schema = &self.schema;
+            let mapped = map_columns(e, &|n| {
+                (0..rel.length()).find(|i| rel.name(*i) == schema.name(n))
+            });
+            if let Some(mapped) = mapped {
+                rel.restrict(&mapped);
+            }
+        }
+    }
+    fn require(&mut self, columns: &[usize]) {
+        let schema = &self.schema;
+        for rel in self.relations.iter_mut() {
+            let mapped: Vec<usize> = columns.iter()
+                .filter_map(|n| (0..rel.length()).find(|i| rel.name(*i) == schema.name(*n)))
+                .collect();
+            rel.require(&mapped);
+        }
+    }
     fn dump_debug_info(&self) {
         println!("==== Union");
     }
@@ -1038,3 +1786,568 @@ fn test_concat() {
     }
 //    println!("done");
 }
+
+#[test]
+fn test_concat_list() {
+    use v2::filebuf::FileBuf;
+    use v2::write2::{write_schema_v2, schema_write};
+
+    let tags = ColumnType::List { inner: Box::new(ColumnType::String) };
+    for (n, fname) in ["/tmp/_list1.dat", "/tmp/_list2.dat"].iter().enumerate() {
+        let mut sch = Schema2::new();
+        sch.add("id", ColumnType::U32le, false);
+        sch.add("tags", tags.clone(), true);
+        let f = File::create(fname).unwrap();
+        let mut fb = FileBuf::new(f, 4096);
+        write_schema_v2(&mut fb, &sch);
+        let row = vec![
+            ColumnValue::U32 { v: n as u32 },
+            ColumnValue::List { v: vec![ColumnValue::String { v: format!("t{}", n) }] },
+        ];
+        assert!(schema_write(&mut fb, row.as_slice(), &sch));
+    }
+
+    let mut co = ConcatRelation::new();
+    assert!(co.add(Box::new(FileRelation::new("/tmp/_list1.dat").unwrap())));
+    assert!(co.add(Box::new(FileRelation::new("/tmp/_list2.dat").unwrap())));
+    let mut prj = Projection::new(Box::new(co), vec!("tags".to_owned()));
+    assert!(prj.ctype(0) == tags);
+
+    let mut n = 0;
+    while prj.read() {
+        let expected = ColumnValue::List { v: vec![ColumnValue::String { v: format!("t{}", n) }] };
+        assert!(*prj.value(0) == expected);
+        n += 1;
+    }
+    assert!(n == 2);
+}
+
+#[test]
+fn test_concat_metadata() {
+    use v2::filebuf::FileBuf;
+    use v2::write2::{write_schema_v2, schema_write};
+
+    for (n, fname) in ["/tmp/_meta1.dat", "/tmp/_meta2.dat"].iter().enumerate() {
+        let mut sch = Schema2::new();
+        sch.add("id", ColumnType::U32le, false);
+        sch.add("host", ColumnType::String, true);
+        sch.set_description(n, Some(&format!("description {}", n)));
+        sch.set_metadata("producer", &format!("producer {}", n));
+        sch.set_metadata(&format!("part{}", n), "yes");
+        let f = File::create(fname).unwrap();
+        let mut fb = FileBuf::new(f, 4096);
+        write_schema_v2(&mut fb, &sch);
+        let row = vec![ColumnValue::U32 { v: n as u32 }, ColumnValue::Null];
+        assert!(schema_write(&mut fb, row.as_slice(), &sch));
+    }
+
+    let mut co = ConcatRelation::new();
+    assert!(co.add(Box::new(FileRelation::new("/tmp/_meta1.dat").unwrap())));
+    assert!(co.add(Box::new(FileRelation::new("/tmp/_meta2.dat").unwrap())));
+    assert!(co.description(0) == Some("description 0".to_owned()));
+    assert!(co.description(1) == Some("description 1".to_owned()));
+    let md = co.metadata();
+    assert!(md.get("producer") == Some(&"producer 0".to_owned()));
+    assert!(md.get("part0").is_some() && md.get("part1").is_some());
+
+    let prj = Projection::new(Box::new(co), vec!("host".to_owned()));
+    assert!(prj.description(0) == Some("description 1".to_owned()));
+}
+
+#[test]
+fn test_bad_header() {
+    use std::io::Write;
+
+    // neither the magic nor the legacy '2'
+    let fname = "/tmp/_bad_header.dat";
+    File::create(fname).unwrap().write_all(b"PK\x03\x04 not a flatfile").unwrap();
+    match FileRelation::new(fname) {
+        Err(e) => assert!(e.kind() == ErrorKind::InvalidData),
+        Ok(_) => panic!("a file without a header was opened"),
+    }
+}
+
+// writes 'rows' to a new file 'fname'
+#[cfg(test)]
+fn write_test_file<I>(fname: &str, sch: &Schema2, options: ::v2::codec::WriteOptions, rows: I)
+    where I: IntoIterator<Item = Vec<ColumnValue>>
+{
+    use v2::filebuf::FileBuf;
+    use v2::writer::Writer;
+
+    let f = File::create(fname).unwrap();
+    let mut w = Writer::create(FileBuf::new(f, 4096), sch, options);
+    for row in rows {
+        assert!(w.write(&row));
+    }
+    w.flush_block();
+    assert!(w.get_mut().flush_all());
+}
+
+#[test]
+fn test_index_skip() {
+    use v2::codec::{BlockOptions, Codec, WriteOptions};
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+    sch.set_index(Some(100));
+
+    let blocks = [None, Some(BlockOptions { rows: 50, bytes: 1 << 20, codec: Codec::Lz4 { level: 0 } })];
+    for (n, blocks) in blocks.iter().enumerate() {
+        let fname = format!("/tmp/_index{}.dat", n);
+        sch.set_blocks(*blocks);
+        write_test_file(&fname, &sch, WriteOptions::new(), (0..1000u64).map(|id| {
+            vec![ColumnValue::U64 { v: id }, ColumnValue::String { v: format!("/srv/{}", id % 9) }]
+        }));
+
+        let f = FileRelation::new(&fname).unwrap();
+        assert!(f.row_count() == Some(1000));
+        // an index block ends with the first block that reaches 100 rows
+        assert!(f.index().unwrap().blocks.len() == 10);
+
+        let e = Expr::Equal { l: Value::Ref { col: 0 }, r: Value::Val { val: ColumnValue::U32 { v: 550 } } };
+        let mut rr = Restriction::new(Box::new(f), e);
+        assert!(rr.read());
+        assert!(*rr.value(0) == ColumnValue::U64 { v: 550 });
+        assert!(*rr.value(1) == ColumnValue::String { v: "/srv/1".to_string() });
+        assert!(!rr.read());
+
+        // without a filter all rows are read
+        let mut f = FileRelation::new(&fname).unwrap();
+        let mut count = 0;
+        while f.read() {
+            assert!(*f.value(0) == ColumnValue::U64 { v: count });
+            count += 1;
+        }
+        assert!(count == 1000 && f.skipped_rows() == 0);
+
+        let mut f = FileRelation::new(&fname).unwrap();
+        f.restrict(&Expr::Greater { l: Value::Ref { col: 0 }, r: Value::Val { val: ColumnValue::U32 { v: 949 } } });
+        let mut count = 0;
+        while f.read() {
+            count += 1;
+        }
+        // rows of the last block are read and filtered, the others skipped
+        assert!(count == 50);
+        assert!(f.skipped_rows() == 900);
+    }
+}
+
+#[test]
+fn test_bloom_skip() {
+    use v2::codec::WriteOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("name", ColumnType::String, false);
+    sch.set_index(Some(100));
+    sch.set_bloom(0, true);
+    sch.set_bloom(1, true);
+
+    // the ids are scattered so that min/max do not exclude any block
+    let id = |f: u64, n: u64| (n * 7919 + f) % 10007;
+    for f in 0..2u64 {
+        write_test_file(&format!("/tmp/_bloom{}.dat", f), &sch, WriteOptions::new(), (0..1000u64).map(|n| {
+            vec![ColumnValue::U64 { v: id(f, n) }, ColumnValue::String { v: format!("user{}", id(f, n)) }]
+        }));
+    }
+
+    let wanted = id(1, 555);
+    let eq = |col: usize, val: ColumnValue| Expr::Equal { l: Value::Ref { col }, r: Value::Val { val } };
+
+    let mut f = FileRelation::new("/tmp/_bloom1.dat").unwrap();
+    assert!(f.index().unwrap().blocks.iter().all(|b| b.blooms[0].is_some()));
+    f.restrict(&eq(0, ColumnValue::U64 { v: wanted }));
+    let mut found = 0;
+    while f.read() {
+        if *f.value(0) == (ColumnValue::U64 { v: wanted }) {
+            found += 1;
+        }
+    }
+    assert!(found == 1);
+    // a block may be read because of a false positive
+    assert!(f.skipped_rows() >= 800);
+
+    // the first file is skipped, as are the other blocks of the second
+    let mut co = ConcatRelation::new();
+    co.add(Box::new(FileRelation::new("/tmp/_bloom0.dat").unwrap()));
+    co.add(Box::new(FileRelation::new("/tmp/_bloom1.dat").unwrap()));
+    let name = ColumnValue::String { v: format!("user{}", wanted) };
+    let mut rr = Restriction::new(Box::new(co), eq(1, name.clone()));
+    assert!(rr.read());
+    assert!(*rr.value(0) == ColumnValue::U64 { v: wanted } && *rr.value(1) == name);
+    assert!(!rr.read());
+
+    let mut f = FileRelation::new("/tmp/_bloom0.dat").unwrap();
+    f.restrict(&eq(1, name));
+    while f.read() {}
+    assert!(f.skipped_rows() >= 800);
+}
+
+#[test]
+fn test_columnar_projection() {
+    use v2::codec::{BlockOptions, Codec, Compression, WriteOptions};
+
+    let mut sch = Schema2::new();
+    for n in 0..40 {
+        sch.add(&format!("c{}", n), ColumnType::U32le, false);
+    }
+    sch.set_blocks(Some(BlockOptions { rows: 128, bytes: 1 << 20, codec: Codec::Lz4 { level: 0 } }));
+    sch.set_columnar(true);
+    sch.set_index(Some(128));
+    let options = WriteOptions::new().compression(Compression::none());
+    write_test_file("/tmp/_columnar.dat", &sch, options, (0..1000u32).map(|row| {
+        (0..40).map(|col| ColumnValue::U32 { v: row * 40 + col }).collect()
+    }));
+
+    // the relations of the DSL read the file as before
+    let def = "a = file \"/tmp/_columnar.dat\"\nb = project a c3 c17\n";
+    let vars = HashMap::new();
+    let mut p = create_relation("b", def, &vars).unwrap();
+    let mut n = 0;
+    while p.read() {
+        assert!(*p.value(0) == ColumnValue::U32 { v: n * 40 + 3 });
+        assert!(*p.value(1) == ColumnValue::U32 { v: n * 40 + 17 });
+        n += 1;
+    }
+    assert!(n == 1000);
+
+    // columns that are not required are not read
+    let mut f = FileRelation::new("/tmp/_columnar.dat").unwrap();
+    f.require(&[5]);
+    assert!(f.read());
+    assert!(*f.value(5) == ColumnValue::U32 { v: 5 } && *f.value(6) == ColumnValue::Null);
+
+    // the restriction adds the columns of its expression
+    let e = Expr::Equal { l: Value::Ref { col: 2 }, r: Value::Val { val: ColumnValue::U32 { v: 40 * 500 + 2 } } };
+    let mut r = Restriction::new(Box::new(FileRelation::new("/tmp/_columnar.dat").unwrap()), e);
+    r.require(&[0]);
+    assert!(r.read());
+    assert!(*r.value(0) == ColumnValue::U32 { v: 40 * 500 } && *r.value(1) == ColumnValue::Null);
+    assert!(!r.read());
+}
+
+#[test]
+fn test_seek_row() {
+    use v2::codec::{BlockOptions, Codec, WriteOptions};
+    use v2::sync::SyncOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("country", ColumnType::String, false);
+    sch.set_dictionary(1, true);
+
+    let country = |n: u64| ColumnValue::String { v: format!("c{}", n % 11) };
+    let blocks = [None, Some(BlockOptions { rows: 100, bytes: 1 << 20, codec: Codec::Zstd { level: 3 } })];
+    for (n, blocks) in blocks.iter().enumerate() {
+        let fname = format!("/tmp/_seek{}.dat", n);
+        sch.set_blocks(*blocks);
+        // the file in block mode has an index, the other one sync records
+        sch.set_index(blocks.map(|_| 1000));
+        sch.set_sync(if blocks.is_some() { None } else { Some(SyncOptions::new(1000)) });
+        write_test_file(&fname, &sch, WriteOptions::new(), (0..10000u64).map(|id| vec![ColumnValue::U64 { v: id }, country(id)]));
+
+        let mut f = FileRelation::new(&fname).unwrap();
+        for row in &[5000u64, 4100, 9999, 0, 4096, 8200] {
+            assert!(f.seek_row(*row as usize));
+            assert!(f.read());
+            assert!(*f.value(0) == ColumnValue::U64 { v: *row } && *f.value(1) == country(*row));
+            assert!(f.row() == *row as usize + 1);
+        }
+        assert!(f.skip(10));
+        assert!(f.read());
+        assert!(*f.value(0) == ColumnValue::U64 { v: 8211 } && *f.value(1) == country(8211));
+
+        // the index of the footer is used if there is one, else the sync
+        // record at row 5000 was seen, the dictionaries are empty there
+        assert!(f.checkpoints.len() == if blocks.is_some() { 10 } else { 2 });
+
+        assert!(f.seek_row(10000));
+        assert!(!f.read());
+        assert!(!f.seek_row(10001));
+        assert!(f.seek_row(1));
+        assert!(f.read() && *f.value(0) == ColumnValue::U64 { v: 1 });
+    }
+}
+
+#[test]
+fn test_reverse() {
+    use v2::codec::{BlockOptions, Codec, WriteOptions};
+    use v2::sync::SyncOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("country", ColumnType::String, false);
+    sch.set_dictionary(1, true);
+
+    let country = |n: u64| ColumnValue::String { v: format!("c{}", n % 11) };
+    let block = BlockOptions { rows: 100, bytes: 1 << 20, codec: Codec::Zstd { level: 3 } };
+    // an index in the footer, sync records and neither
+    let layouts = [(Some(block), Some(1000), None), (None, None, Some(SyncOptions::new(500))), (None, None, None)];
+    for (n, &(blocks, index, sync)) in layouts.iter().enumerate() {
+        let fname = format!("/tmp/_reverse{}.dat", n);
+        sch.set_blocks(blocks);
+        sch.set_index(index);
+        sch.set_sync(sync);
+        write_test_file(&fname, &sch, WriteOptions::new(), (0..10000u64).map(|id| vec![ColumnValue::U64 { v: id }, country(id)]));
+
+        let r = ReverseRelation::new(FileRelation::new(&fname).unwrap());
+        if index.is_none() && sync.is_none() {
+            // the rows would have to be read from the start
+            assert!(r.is_err());
+            continue;
+        }
+        let mut r = r.unwrap();
+        for id in (0..10000u64).rev() {
+            assert!(r.read());
+            assert!(*r.value(0) == ColumnValue::U64 { v: id } && *r.value(1) == country(id));
+        }
+        assert!(!r.read());
+    }
+}
+
+#[test]
+fn test_value_ref() {
+    use v2::codec::{Codec, Compression, WriteOptions};
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+
+    let path = |n: u64| match n % 10 {
+        0 => ColumnValue::Null,
+        _ => ColumnValue::String { v: format!("/var/lib/{}", "x".repeat(n as usize % 50)) },
+    };
+    // strings are only left in the file if they are not compressed
+    let compression = [Compression::none(), Compression::single(Codec::Zstd { level: 3 })];
+    for (n, compression) in compression.iter().enumerate() {
+        let fname = format!("/tmp/_valueref{}.dat", n);
+        let options = WriteOptions::new().compression(*compression);
+        write_test_file(&fname, &sch, options, (0..1000u64).map(|id| vec![ColumnValue::U64 { v: id }, path(id)]));
+
+        // the path the filters are evaluated on
+        let mut f = FileRelation::new(&fname).unwrap();
+        let mut borrowed = 0;
+        for id in 0..1000u64 {
+            assert!(f.read_current(true).is_ok());
+            if f.spans[1].is_some() {
+                borrowed += 1;
+            }
+            assert!(f.value_ref(1).to_value() == path(id));
+            assert!(f.value_ref(0) == ColumnValueRef::Value { v: &ColumnValue::U64 { v: id } });
+            assert!(*f.value(1) == path(id));
+        }
+        assert!(f.read_current(true).is_err());
+        // short strings are not compressed either
+        assert!(if n == 0 { borrowed == 900 } else { borrowed > 0 && borrowed < 900 });
+
+        // read() leaves the strings in the file too, value() copies them
+        let mut f = FileRelation::new(&fname).unwrap();
+        let mut copied = 0;
+        for id in 0..1000u64 {
+            assert!(f.read());
+            if let Some(span) = f.spans[1] {
+                match f.value_ref(1) {
+                    ColumnValueRef::String { v } => assert!(v.as_ptr() == f.m.bytes(span.0, span.1).as_ptr()),
+                    _ => panic!("expected a string"),
+                }
+                assert!(f.strings[1].get().is_none());
+                assert!(*f.value(1) == path(id));
+                copied += 1;
+            }
+            assert!(f.value_ref(1).to_value() == path(id) && *f.value(1) == path(id));
+        }
+        assert!(!f.read());
+        assert!(copied == borrowed);
+
+        let e = Expr::Equal { l: Value::Ref { col: 1 }, r: Value::Val { val: path(49) } };
+        let mut rr = Restriction::new(Box::new(FileRelation::new(&fname).unwrap()), e);
+        let mut ids = Vec::new();
+        while rr.read() {
+            assert!(*rr.value(1) == path(49));
+            ids.push(rr.value(0).clone());
+        }
+        assert!(ids.len() == 20 && ids[1] == ColumnValue::U64 { v: 99 });
+
+        let e = Expr::IsNull { l: Value::Ref { col: 1 } };
+        let mut rr = Restriction::new(Box::new(FileRelation::new(&fname).unwrap()), e);
+        let mut count = 0;
+        while rr.read() {
+            count += 1;
+        }
+        assert!(count == 100);
+    }
+}
+
+#[test]
+fn test_read_batch() {
+    use std::cmp::min;
+    use types::ColumnVector;
+    use v2::codec::{BlockOptions, Codec, WriteOptions};
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    sch.add("path", ColumnType::String, true);
+
+    let path = |n: u64| match n % 7 {
+        0 => ColumnValue::Null,
+        _ => ColumnValue::String { v: format!("/opt/{}", n % 5) },
+    };
+    // a file in rows and one in row groups
+    let blocks = [None, Some(BlockOptions { rows: 64, bytes: 1 << 20, codec: Codec::Lz4 { level: 0 } })];
+    for (n, blocks) in blocks.iter().enumerate() {
+        sch.set_blocks(*blocks);
+        sch.set_columnar(blocks.is_some());
+        let fname = format!("/tmp/_batch{}.dat", n);
+        write_test_file(&fname, &sch, WriteOptions::new(), (0..1000u64).map(|id| vec![ColumnValue::U64 { v: id }, path(id)]));
+    }
+
+    let mut vector = ColumnVector::new();
+    for id in 0..20 {
+        vector.push(path(id));
+    }
+    assert!(vector.nulls == vec![0x81, 0x40, 0x00]);
+    assert!(vector.is_null(14) && !vector.is_null(15));
+    let mut values: Vec<ColumnValue> = (20..30).map(path).collect();
+    vector.append(&mut values);
+    assert!(values.is_empty() && vector.len() == 30);
+    assert!(vector.is_null(21) && vector.is_null(28) && !vector.is_null(22));
+    let keep: Vec<bool> = (0..30).map(|row| row % 2 == 0).collect();
+    vector.retain(&keep);
+    assert!(vector.len() == 15 && *vector.values.last().unwrap() == path(28));
+    assert!(vector.nulls == vec![0x81, 0x40]);
+
+    for n in 0..2 {
+        let mut f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        let mut batch = Batch::new();
+        let mut id = 0;
+        loop {
+            let rows = f.read_batch(&mut batch, 300);
+            assert!(rows == batch.rows() && rows == min(300, 1000 - id as usize));
+            if rows == 0 {
+                break;
+            }
+            for row in 0..rows {
+                assert!(*batch.value(row, 0) == ColumnValue::U64 { v: id });
+                assert!(*batch.value(row, 1) == path(id));
+                assert!(batch.columns[1].is_null(row) == (path(id) == ColumnValue::Null));
+                assert!(!batch.columns[0].is_null(row));
+                id += 1;
+            }
+        }
+        assert!(id == 1000);
+
+        // columns in a different order, twice and missing
+        let f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        let cols = vec!["path".to_string(), "id".to_string(), "size".to_string(), "path".to_string()];
+        let mut p = Projection::new(Box::new(f), cols);
+        assert!(p.read_batch(&mut batch, 10) == 10);
+        assert!(batch.columns.len() == 4);
+        assert!(*batch.value(3, 0) == path(3) && *batch.value(3, 1) == ColumnValue::U64 { v: 3 });
+        assert!(batch.columns[2].is_null(3) && *batch.value(3, 3) == path(3));
+
+        let f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        let e = Expr::Equal { l: Value::Ref { col: 1 }, r: Value::Val { val: path(3) } };
+        let mut r = Restriction::new(Box::new(f), e);
+        assert!(r.read_batch(&mut batch, 1000) == 172);
+        assert!(*batch.value(1, 0) == ColumnValue::U64 { v: 8 });
+
+        // the batches are filled up with the rows that match
+        let f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        let e = Expr::Equal { l: Value::Ref { col: 1 }, r: Value::Val { val: path(3) } };
+        let mut r = Restriction::new(Box::new(f), e);
+        let mut rows = Vec::new();
+        while r.read_batch(&mut batch, 50) > 0 {
+            assert!((0..batch.rows()).all(|row| *batch.value(row, 1) == path(3)));
+            rows.push(batch.rows());
+        }
+        assert!(rows == vec![50, 50, 50, 22]);
+
+        let f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        let mut u = UniqueRelation::new(Box::new(f), vec!["path".to_string()]);
+        assert!(u.read_batch(&mut batch, 4) == 4);
+        assert!(u.read_batch(&mut batch, 1000) == 2);
+        assert!(*batch.value(0, 1) == path(4) && *batch.value(1, 1) == path(5));
+        assert!(u.read_batch(&mut batch, 1000) == 0);
+
+        // after rows read one by one the rest of the row group is moved
+        let mut f = FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap();
+        for _ in 0..10 {
+            assert!(f.read());
+        }
+        assert!(f.read_batch(&mut batch, 100) == 100);
+        assert!(*batch.value(0, 0) == ColumnValue::U64 { v: 10 } && *batch.value(99, 1) == path(109));
+        assert!(f.read());
+        assert!(*f.value(0) == ColumnValue::U64 { v: 110 });
+    }
+
+    let mut c = ConcatRelation::new();
+    for n in 0..2 {
+        assert!(c.add(Box::new(FileRelation::new(&format!("/tmp/_batch{}.dat", n)).unwrap())));
+    }
+    let mut batch = Batch::new();
+    let mut rows = Vec::new();
+    loop {
+        let n = c.read_batch(&mut batch, 384);
+        if n == 0 {
+            break;
+        }
+        rows.push(n);
+        match batch.value(n - 1, 0) {
+            ColumnValue::U64 { v } => assert!(*batch.value(n - 1, 1) == path(*v)),
+            _ => panic!("expected an id"),
+        }
+    }
+    // the batches stop at the end of each file
+    assert!(rows == vec![384, 384, 232, 384, 384, 232]);
+}
+
+#[test]
+fn test_zoned_timestamp() {
+    use types::TimeUnit;
+    use v2::codec::WriteOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("utc", ColumnType::Timestamp { unit: TimeUnit::Seconds }, false);
+    sch.add("local", ColumnType::Timestamp { unit: TimeUnit::Seconds }, false);
+    sch.set_timezone(1, Some("Europe/Prague"));
+    write_test_file("/tmp/_zoned.dat", &sch, WriteOptions::new(), (0..10i64).map(|day| {
+        let ts = ColumnValue::Timestamp { v: 1546300800 + day * 86400, unit: TimeUnit::Seconds };
+        vec![ts.clone(), ts]
+    }));
+
+    let date = |d: &str| Value::Val { val: ColumnValue::String { v: d.to_string() } };
+    let rows = |e: Expr| {
+        // the same with and without resolve_timestamps
+        let mut f = FileRelation::new("/tmp/_zoned.dat").unwrap();
+        let mut evaluated = 0;
+        while f.read() {
+            if eval(&f, &e) {
+                evaluated += 1;
+            }
+        }
+        let mut r = Restriction::new(Box::new(FileRelation::new("/tmp/_zoned.dat").unwrap()), e);
+        let mut n = 0;
+        while r.read() {
+            n += 1;
+        }
+        assert!(n == evaluated);
+        n
+    };
+    let count = |col: usize, d: &str| rows(Expr::Equal { l: Value::Ref { col }, r: date(d) });
+    // strings without an offset are times in the zone of the column
+    assert!(count(0, "2019-01-02T00:00:00") == 1);
+    assert!(count(1, "2019-01-02T00:00:00") == 0);
+    assert!(count(1, "2019-01-02T01:00:00") == 1);
+    assert!(count(1, "2019-01-02") == 0);
+    assert!(count(1, "2019-01-02T00:00:00Z") == 1);
+    assert!(count(1, "2019-01-02T01:00:00+01:00") == 1);
+    assert!(rows(Expr::NotEqual { l: Value::Ref { col: 1 }, r: date("2019-01-02T01:00:00") }) == 9);
+    assert!(rows(Expr::Less { l: Value::Ref { col: 1 }, r: date("2019-01-03") }) == 2);
+
+    // integers are in the unit of the column
+    let second = |day: u64| Value::Val { val: ColumnValue::U64 { v: 1546300800 + day * 86400 } };
+    assert!(rows(Expr::Greater { l: Value::Ref { col: 0 }, r: second(5) }) == 4);
+    assert!(rows(Expr::Equal { l: second(2), r: Value::Ref { col: 1 } }) == 1);
+}
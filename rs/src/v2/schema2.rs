@@ -1,4 +1,9 @@
 use types::{ColumnType};
+use v2::codec::{BlockOptions, Compression, ZstdDictionary};
+use v2::checksum::{Checksum, ChecksumGranularity};
+use v2::sync::SyncOptions;
+use v2::bloom::supports_bloom;
+use std::collections::BTreeMap;
 
 pub trait Schema {
     fn len(&self) -> usize;
@@ -7,11 +12,47 @@ pub trait Schema {
     fn nullable(&self, index: usize) -> bool;
 }
 
+// how the values of a column are stored in the rows
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Encoding {
+    Plain,
+    Dictionary, // string columns, values are indexes into a dictionary
+    Varint,     // integer columns, signed values are zigzag encoded
+}
+
+impl Encoding {
+    // true if values of type ctype can be stored with this encoding
+    pub fn supports(&self, ctype: &ColumnType) -> bool {
+        match self {
+            Encoding::Plain => true,
+            Encoding::Dictionary => *ctype == ColumnType::String,
+            Encoding::Varint => match ctype {
+                ColumnType::U32le | ColumnType::U64le |
+                ColumnType::I32le | ColumnType::I64le => true,
+                _ => false,
+            },
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Schema2 {
     pub names: Vec<String>,
     pub types: Vec<ColumnType>,
     pub nullable: Vec<bool>,
+    pub timezones: Vec<Option<String>>, // only used by timestamp columns
+    pub encodings: Vec<Encoding>,
+    pub descriptions: Vec<Option<String>>,
+    pub metadata: BTreeMap<String, String>, // producer, creation time, lineage etc.
+    pub compression: Vec<Option<Compression>>, // overrides WriteOptions, not stored in the file
+    pub zstd_dictionary: Option<ZstdDictionary>, // used by Codec::ZstdDict, stored in the header
+    pub blocks: Option<BlockOptions>, // only block mode is stored in the header, not the sizes
+    pub columnar: bool, // blocks store the values column by column
+    pub checksum: Checksum,
+    pub checksum_granularity: ChecksumGranularity,
+    pub sync: Option<SyncOptions>, // only the marker is stored in the header
+    pub index_rows: Option<usize>, // rows per index block, the index is written as a footer
+    pub bloom: Vec<bool>, // Bloom filters in the index, not stored in the header
 }
 
 impl Schema2 {
@@ -20,6 +61,19 @@ impl Schema2 {
             names: Vec::new(),
             types: Vec::new(),
             nullable: Vec::new(),
+            timezones: Vec::new(),
+            encodings: Vec::new(),
+            descriptions: Vec::new(),
+            metadata: BTreeMap::new(),
+            compression: Vec::new(),
+            zstd_dictionary: None,
+            blocks: None,
+            columnar: false,
+            checksum: Checksum::Adler32,
+            checksum_granularity: ChecksumGranularity::Group,
+            sync: None,
+            index_rows: None,
+            bloom: Vec::new(),
         }
     }
 
@@ -30,11 +84,114 @@ impl Schema2 {
         self.names.push(name.to_string());
         self.types.push(ctype);
         self.nullable.push(nullable);
+        self.timezones.push(None);
+        self.encodings.push(Encoding::Plain);
+        self.descriptions.push(None);
+        self.compression.push(None);
+        self.bloom.push(false);
     }
 
     pub fn set_nullable(&mut self, index: usize, nullability: bool) {
         self.nullable[index] = nullability;
     }
+
+    pub fn set_timezone(&mut self, index: usize, timezone: Option<&str>) {
+        self.timezones[index] = timezone.map(|tz| tz.to_string());
+    }
+
+    pub fn set_encoding(&mut self, index: usize, encoding: Encoding) {
+        assert!(encoding.supports(&self.types[index]));
+        self.encodings[index] = encoding;
+    }
+
+    // store the values of a string column as indexes into a dictionary
+    // built while writing, for columns with few distinct values
+    pub fn set_dictionary(&mut self, index: usize, dictionary: bool) {
+        self.set_encoding(index, if dictionary { Encoding::Dictionary } else { Encoding::Plain });
+    }
+
+    pub fn encoding(&self, index: usize) -> Encoding {
+        self.encodings[index]
+    }
+
+    pub fn set_description(&mut self, index: usize, description: Option<&str>) {
+        self.descriptions[index] = description.map(|d| d.to_string());
+    }
+
+    pub fn description(&self, index: usize) -> Option<&str> {
+        self.descriptions[index].as_ref().map(|d| d.as_str())
+    }
+
+    pub fn set_compression(&mut self, index: usize, compression: Option<Compression>) {
+        self.compression[index] = compression;
+    }
+
+    pub fn compression(&self, index: usize) -> Option<Compression> {
+        self.compression[index]
+    }
+
+    pub fn set_zstd_dictionary(&mut self, dictionary: Option<Vec<u8>>) {
+        self.zstd_dictionary = dictionary.map(ZstdDictionary::new);
+    }
+
+    pub fn zstd_dictionary(&self) -> Option<&[u8]> {
+        self.zstd_dictionary.as_ref().map(|d| d.bytes())
+    }
+
+    // the digested dictionary the values are compressed with
+    pub fn prepared_zstd_dictionary(&self) -> Option<&ZstdDictionary> {
+        self.zstd_dictionary.as_ref()
+    }
+
+    // write the rows in compressed blocks, the values in a block are best
+    // written with Compression::none()
+    pub fn set_blocks(&mut self, blocks: Option<BlockOptions>) {
+        self.blocks = blocks;
+    }
+
+    // store each block as a row group with the values of every column
+    // together, so that readers can skip the columns they do not need.
+    // Needs set_blocks
+    pub fn set_columnar(&mut self, columnar: bool) {
+        self.columnar = columnar;
+    }
+
+    // checksum of the rows, adler32 per group of 8 columns by default
+    pub fn set_checksum(&mut self, checksum: Checksum, granularity: ChecksumGranularity) {
+        self.checksum = checksum;
+        self.checksum_granularity = granularity;
+    }
+
+    // write sync records that let readers skip over corrupt data
+    pub fn set_sync(&mut self, sync: Option<SyncOptions>) {
+        self.sync = sync;
+    }
+
+    // write a footer with the number of rows and min/max statistics of
+    // every block of rows, in block mode every compressed block is indexed
+    // and 'rows' is not used
+    pub fn set_index(&mut self, rows: Option<usize>) {
+        self.index_rows = rows;
+    }
+
+    // keep a Bloom filter of the values of the column in every index block,
+    // for lookups of single values. Needs set_index
+    pub fn set_bloom(&mut self, index: usize, bloom: bool) {
+        assert!(!bloom || supports_bloom(&self.types[index]));
+        self.bloom[index] = bloom;
+    }
+
+    pub fn set_metadata(&mut self, key: &str, value: &str) {
+        self.metadata.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn get_metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(|v| v.as_str())
+    }
+
+    pub fn timezone(&self, index: usize) -> Option<&str> {
+        self.timezones[index].as_ref().map(|tz| tz.as_str())
+    }
 }
 
 impl Schema for Schema2 {
@@ -47,7 +204,7 @@ impl Schema for Schema2 {
     }
 
     fn ctype(&self, index: usize) -> ColumnType {
-        self.types[index]
+        self.types[index].clone()
     }
 
     fn nullable(&self, index: usize) -> bool {
@@ -0,0 +1,53 @@
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+extern crate tiny_keccak;
+use self::tiny_keccak::{Hasher, Shake};
+
+pub const SYNC_MARKER_LEN: usize = 16;
+
+// rows between sync records when appending to a file, the interval the
+// file was written with is not known
+pub const DEFAULT_SYNC_INTERVAL: usize = 4096;
+
+static MARKERS_CREATED: AtomicUsize = AtomicUsize::new(0);
+
+// a sync record is written every 'interval' rows, a reader that finds
+// corrupt data skips to the next record and continues from there.
+// the marker is stored in the header, the interval is not
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyncOptions {
+    pub marker: [u8; SYNC_MARKER_LEN],
+    pub interval: usize,
+}
+
+impl SyncOptions {
+    // a new random marker, unlikely to appear in the data of the file
+    pub fn new(interval: usize) -> SyncOptions {
+        SyncOptions {
+            marker: random_marker(),
+            interval,
+        }
+    }
+}
+
+fn random_marker() -> [u8; SYNC_MARKER_LEN] {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut k = Shake::v128();
+    k.update(&now.as_secs().to_le_bytes());
+    k.update(&now.subsec_nanos().to_le_bytes());
+    k.update(&process::id().to_le_bytes());
+    k.update(&MARKERS_CREATED.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    let mut marker = [0; SYNC_MARKER_LEN];
+    k.finalize(&mut marker);
+    marker
+}
+
+#[test]
+fn test_random_marker() {
+    let a = SyncOptions::new(1000);
+    let b = SyncOptions::new(1000);
+    assert!(a.marker != b.marker);
+    assert!(a.interval == 1000);
+}
@@ -0,0 +1,238 @@
+use types::TimeUnit;
+use std::convert::TryFrom;
+
+extern crate chrono;
+extern crate chrono_tz;
+use self::chrono::{DateTime, Offset, TimeZone};
+use self::chrono_tz::Tz;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+// nanoseconds in one tick of the given unit
+fn nanos_per_unit(unit: TimeUnit) -> i128 {
+    match unit {
+        TimeUnit::Seconds => NANOS_PER_SECOND,
+        TimeUnit::Millis => 1_000_000,
+        TimeUnit::Micros => 1_000,
+        TimeUnit::Nanos => 1,
+    }
+}
+
+// timestamp value converted to nanoseconds since the unix epoch
+pub fn to_nanos(v: i64, unit: TimeUnit) -> i128 {
+    (v as i128) * nanos_per_unit(unit)
+}
+
+// days since 1970-01-01 for a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+struct Cursor<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn eos(&self) -> bool {
+        self.pos >= self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // exactly n decimal digits
+    fn digits(&mut self, n: usize) -> Option<i64> {
+        let mut r = 0;
+        for _ in 0..n {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => {
+                    r = r * 10 + (c - b'0') as i64;
+                    self.pos += 1;
+                }
+                _ => return None,
+            }
+        }
+        Some(r)
+    }
+}
+
+// parse an ISO-8601 timestamp into nanoseconds since the unix epoch
+//
+// accepts 'YYYY-MM-DD', optionally followed by 'T' (or a space) and
+// 'HH:MM[:SS[.fraction]]' and an offset of 'Z' or '+HH:MM' / '-HH:MM'.
+// timestamps without an offset are taken to be UTC.
+pub fn parse_iso8601(s: &str) -> Option<i128> {
+    parse_iso8601_offset(s).map(|(nanos, _)| nanos)
+}
+
+// like parse_iso8601, also tells if the string has an offset
+pub fn parse_iso8601_offset(s: &str) -> Option<(i128, bool)> {
+    let mut c = Cursor { s: s.trim().as_bytes(), pos: 0 };
+
+    let year = c.digits(4)?;
+    if !c.expect(b'-') {
+        return None;
+    }
+    let month = c.digits(2)?;
+    if !c.expect(b'-') {
+        return None;
+    }
+    let day = c.digits(2)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds = days_from_civil(year, month, day) as i128 * 86400;
+    let mut nanos: i128 = 0;
+    let mut has_offset = false;
+
+    if c.expect(b'T') || c.expect(b' ') {
+        let hour = c.digits(2)?;
+        if !c.expect(b':') {
+            return None;
+        }
+        let minute = c.digits(2)?;
+        let second = if c.expect(b':') { c.digits(2)? } else { 0 };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        seconds += (hour * 3600 + minute * 60 + second) as i128;
+
+        if c.expect(b'.') || c.expect(b',') {
+            let mut scale = NANOS_PER_SECOND / 10;
+            let mut ndigits = 0;
+            while let Some(d) = c.digits(1) {
+                // digits beyond nanosecond precision are dropped
+                nanos += d as i128 * scale;
+                scale /= 10;
+                ndigits += 1;
+            }
+            if ndigits == 0 {
+                return None;
+            }
+        }
+
+        if c.expect(b'Z') {
+            has_offset = true; // utc
+        } else if let Some(sign) = c.peek() {
+            if sign != b'+' && sign != b'-' {
+                return None;
+            }
+            c.pos += 1;
+            let oh = c.digits(2)?;
+            c.expect(b':');
+            let om = c.digits(2)?;
+            let offset = (oh * 3600 + om * 60) as i128;
+            has_offset = true;
+            // local time = utc + offset
+            if sign == b'+' {
+                seconds -= offset;
+            } else {
+                seconds += offset;
+            }
+        }
+    }
+
+    if !c.eos() {
+        return None;
+    }
+    Some((seconds * NANOS_PER_SECOND + nanos, has_offset))
+}
+
+// like parse_iso8601, a timestamp without an offset is a wall clock time
+// in 'zone', a tz database name. Zones the database does not know are
+// taken to be UTC, like columns without a zone
+pub fn parse_iso8601_in(s: &str, zone: &str) -> Option<i128> {
+    match parse_iso8601_offset(s)? {
+        (nanos, true) => Some(nanos),
+        (local, false) => Some(local - zone_offset(zone, local).unwrap_or(0) as i128 * NANOS_PER_SECOND),
+    }
+}
+
+// the offset of 'zone' from UTC in seconds at the wall clock time 'local',
+// in nanoseconds as if it were UTC. The earlier offset is used when the
+// clocks are put back, in the hour skipped when they are put forward the
+// offset at 'local' in UTC
+fn zone_offset(zone: &str, local: i128) -> Option<i32> {
+    let tz: Tz = zone.parse().ok()?;
+    let seconds = i64::try_from(local.div_euclid(NANOS_PER_SECOND)).ok()?;
+    let local = DateTime::from_timestamp(seconds, 0)?.naive_utc();
+    let offset = match tz.offset_from_local_datetime(&local).earliest() {
+        Some(offset) => offset,
+        None => tz.offset_from_utc_datetime(&local),
+    };
+    Some(offset.fix().local_minus_utc())
+}
+
+#[test]
+fn test_parse_iso8601() {
+    assert!(parse_iso8601("1970-01-01") == Some(0));
+    assert!(parse_iso8601("1970-01-02T00:00:00Z") == Some(86400 * NANOS_PER_SECOND));
+    assert!(parse_iso8601("2000-03-01T12:30:15Z") == Some(951913815 * NANOS_PER_SECOND));
+    assert!(parse_iso8601("2000-03-01 12:30:15") == Some(951913815 * NANOS_PER_SECOND));
+    assert!(parse_iso8601("2000-03-01T14:30:15+02:00") == Some(951913815 * NANOS_PER_SECOND));
+    assert!(parse_iso8601("1969-12-31T23:59:59.5Z") == Some(-NANOS_PER_SECOND / 2));
+    assert!(parse_iso8601("2019-06-01T00:00:00.000000001Z").unwrap() % NANOS_PER_SECOND == 1);
+    assert!(parse_iso8601("2019-13-01").is_none());
+    assert!(parse_iso8601("2019-02-31").is_none());
+    assert!(parse_iso8601("2019-04-31").is_none());
+    assert!(parse_iso8601("2019-02-29").is_none());
+    assert!(parse_iso8601("2020-02-29").is_some());
+    assert!(parse_iso8601("1900-02-29").is_none());
+    assert!(parse_iso8601("2000-02-29").is_some());
+    assert!(parse_iso8601_offset("2000-03-01T12:30:15") == Some((951913815 * NANOS_PER_SECOND, false)));
+    assert!(parse_iso8601_offset("2000-03-01T12:30:15Z") == Some((951913815 * NANOS_PER_SECOND, true)));
+    assert!(parse_iso8601_offset("2000-03-01T14:30:15+02:00") == Some((951913815 * NANOS_PER_SECOND, true)));
+    assert!(parse_iso8601("2019-06-01T00:00:00Zjunk").is_none());
+    assert!(parse_iso8601("yesterday").is_none());
+}
+
+#[test]
+fn test_parse_iso8601_in() {
+    let utc = |s: &str| parse_iso8601(s).unwrap();
+    // winter and summer time
+    assert!(parse_iso8601_in("2019-01-02T01:00:00", "Europe/Prague") == Some(utc("2019-01-02T00:00:00Z")));
+    assert!(parse_iso8601_in("2019-07-02T02:00:00", "Europe/Prague") == Some(utc("2019-07-02T00:00:00Z")));
+    assert!(parse_iso8601_in("2019-01-02", "America/New_York") == Some(utc("2019-01-02T05:00:00Z")));
+    // an offset in the string wins
+    assert!(parse_iso8601_in("2019-01-02T01:00:00Z", "Europe/Prague") == Some(utc("2019-01-02T01:00:00Z")));
+    // 02:30 happens twice when the clocks are put back
+    assert!(parse_iso8601_in("2019-10-27T02:30:00", "Europe/Prague") == Some(utc("2019-10-27T00:30:00Z")));
+    assert!(parse_iso8601_in("2019-01-02T01:00:00", "Not/AZone") == Some(utc("2019-01-02T01:00:00Z")));
+    assert!(parse_iso8601_in("yesterday", "Europe/Prague").is_none());
+}
+
+#[test]
+fn test_to_nanos() {
+    assert!(to_nanos(1500, TimeUnit::Millis) == to_nanos(1500000, TimeUnit::Micros));
+    assert!(to_nanos(-2, TimeUnit::Seconds) == -2 * NANOS_PER_SECOND);
+}
@@ -18,6 +18,15 @@ impl Vecbuf {
         }
     }
 
+    // read from the bytes of v
+    pub fn from_vec(v: Vec<u8>) -> Vecbuf {
+        Vecbuf {
+            buf: v,
+            pos: 0,
+            eof: false,
+        }
+    }
+
     pub fn reset(&mut self) {
         self.pos = 0;
     }
@@ -41,6 +50,11 @@ impl ReadBuf for Vecbuf {
         self.pos
     }
 
+    #[inline]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
     #[inline]
     fn readb(&mut self) -> u8 {
         if self.pos < self.len() {
@@ -48,6 +62,7 @@ impl ReadBuf for Vecbuf {
             self.pos += 1;
             r
         } else {
+            self.eof = true;
             0 as u8
         }
     }
@@ -75,3 +90,15 @@ impl AppendBuf for Vecbuf {
         }
     }
 }
+
+// a growing buffer, used to collect the rows of a block
+impl AppendBuf for Vec<u8> {
+    #[inline]
+    fn flush(&mut self) {
+    }
+
+    #[inline]
+    fn writeb(&mut self, b: u8) {
+        self.push(b);
+    }
+}
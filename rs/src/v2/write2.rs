@@ -1,33 +1,43 @@
-use types::{ColumnType, ColumnValue};
+use types::{ColumnType, ColumnValue, TimeUnit};
 use std::str;
-use std::io::{Read, Write};
 use std::cmp::{min};
 use std::sync::atomic::{AtomicBool, Ordering};
-use v2::schema2::{Schema, Schema2};
+use v2::schema2::{Encoding, Schema, Schema2};
 use v2::buf::{ReadBuf, AppendBuf};
-use v2::adlerbuf::{ReadBufAdler32, AppendBufAdler32};
+use v2::checksum::{ReadBufChecksum, AppendBufChecksum, Checksum, ChecksumGranularity};
 
 use std::fs::{File, OpenOptions};
 use v2::filebuf::{FileBuf, ReadFileBuf};
 use v2::vecbuf::Vecbuf;
 
 use v2::err::SchemaReadError;
+use v2::decimal;
+use v2::codec::{self, BlockOptions, Codec, Compression, WriteOptions, ZstdDictionary};
+use v2::dict::{Dictionaries, DICT_CODE_FIRST_INDEX};
+use v2::sync::{SyncOptions, SYNC_MARKER_LEN, DEFAULT_SYNC_INTERVAL};
+use v2::index::{BlockIndex, ColumnStats, FileIndex, DEFAULT_INDEX_ROWS};
+use v2::bloom::Bloom;
 
-extern crate lz4;
-extern crate zstd;
 
 //use proptest::prelude::any;
 
 //#[macro_use] extern crate proptest;
 //use proptest::prelude::*;
 
-fn read_varint<B: ReadBuf>(b: &mut B) -> usize {
+pub fn read_varint<B: ReadBuf>(b: &mut B) -> usize {
+    read_varint64(b) as usize
+}
+
+// varints of values are 64 bits wide on every target
+fn read_varint64<B: ReadBuf>(b: &mut B) -> u64 {
     let mut bits: usize = 0;
-    let mut r : usize = 0;
+    let mut r : u64 = 0;
     loop {
         let u = read_db(b);
-        let v = (u & 0x7f) as usize;
-        r |= v << bits;
+        let v = (u & 0x7f) as u64;
+        if bits < 64 { // more bits only in corrupt data
+            r |= v << bits;
+        }
         bits += 7;
         if u & 128 == 0 {
             break
@@ -37,7 +47,11 @@ fn read_varint<B: ReadBuf>(b: &mut B) -> usize {
 }
 
 // writes at least a byte
-fn write_varint<B: AppendBuf>(b: &mut B, v: usize) {
+pub fn write_varint<B: AppendBuf>(b: &mut B, v: usize) {
+    write_varint64(b, v as u64)
+}
+
+fn write_varint64<B: AppendBuf>(b: &mut B, v: u64) {
     let mut r = v;
     loop {
         let mut x7 = (r & 0x7f) as u8;
@@ -52,124 +66,152 @@ fn write_varint<B: AppendBuf>(b: &mut B, v: usize) {
     }
 }
 
-fn read_varstring<B: ReadBuf>(b: &mut B) -> Result<String, SchemaReadError> {
+fn read_varbytes<B: ReadBuf>(b: &mut B) -> Result<Vec<u8>, SchemaReadError> {
+    read_varbytes_with(b, None)
+}
+
+// read a variable sized byte sequence, decompressing it if needed,
+// 'dictionary' is the zstd dictionary of the file
+fn read_varbytes_with<B: ReadBuf>(b: &mut B, dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>, SchemaReadError> {
     let co = read_db(b);
-    if co == 0 as u8 { // no compression
-        let size = read_varint(b);
-        let mut bytes = Vec::new();
-        for i in 0..size {
-            let byte = read_db(b);
-            bytes.push(byte);
-        }
-        // convert bytes to string
-	match str::from_utf8(bytes.as_slice()) {
-	      Ok(x) => Ok(x.to_string()),
-	      Err(e) => Err(SchemaReadError::BadUtf8)
-        }
-    } else if co == 'Z' as u8 {
-        let size = read_varint(b);
-        let mut bytes = Vec::new();
-        for i in 0..size {
-            let byte = read_db(b);
-            bytes.push(byte);
-        }
-        let mut d = zstd::Decoder::new(bytes.as_slice()).unwrap();
-        let mut dbuf: Vec<u8> = Vec::new();
-        let r = d.read_to_end(&mut dbuf);
-        match r {
-            Ok(_rdsize) => {
-                match str::from_utf8(&dbuf) {
-                    Ok(s) => Ok(s.to_string()),
-                    Err(e) => Err(SchemaReadError::BadUtf8)
+    let size = read_varint(b);
+    read_sized_bytes(b, co, size, dictionary)
+}
+
+// the 'size' bytes after the compression marker 'co' and the size
+fn read_sized_bytes<B: ReadBuf>(b: &mut B, co: u8, size: usize, dictionary: Option<&ZstdDictionary>) -> Result<Vec<u8>, SchemaReadError> {
+    let mut bytes = Vec::new();
+    for _ in 0..size {
+        if b.past_eof() {
+            // a corrupt size can be much larger than the file
+            return Err(SchemaReadError::UnexpectedEof);
+        }
+        let byte = read_db(b);
+        bytes.push(byte);
+    }
+    if co == codec::MARKER_NONE { // no compression
+        return Ok(bytes);
+    }
+    match codec::find_codec(co) {
+        Some(entry) => {
+            match (entry.decompress)(bytes.as_slice(), dictionary) {
+                Ok(dbuf) => Ok(dbuf),
+                Err(e) => {
+                    static DO_PRINT_DECOMP_ERROR : AtomicBool = AtomicBool::new(false);
+                    if DO_PRINT_DECOMP_ERROR.load(Ordering::Relaxed) {
+                        println!("read_varstring: {}/decompression error: {:?}", co as char, e);
+                        DO_PRINT_DECOMP_ERROR.store(true, Ordering::Relaxed);
+                    }
+                    Err(SchemaReadError::DecompressionError)
                 }
             }
-            Err(e) => {
-                static DO_PRINT_DECOMP_ERROR : AtomicBool = AtomicBool::new(false);
-		if DO_PRINT_DECOMP_ERROR.load(Ordering::Relaxed) {
-                    println!("read_varstring: Z/decompression error: {:?}", e);
-		    DO_PRINT_DECOMP_ERROR.store(true, Ordering::Relaxed);
-                }
-                Err(SchemaReadError::DecompressionError)
-            }
-        }
-    } else if co == 'L' as u8 {
-        let size = read_varint(b);
-//        if !check(b, size) {
-//            return None;
-//        }
-        let mut bytes = Vec::new();
-        for i in 0..size {
-            let byte = read_db(b);
-            bytes.push(byte);
-        }
-        let mut d = lz4::Decoder::new(bytes.as_slice()).unwrap();
-        let mut dbuf: Vec<u8> = Vec::new();
-        let r = d.read_to_end(&mut dbuf);
-        d.finish();
-        match(r) {
-	    Ok(_) => {
-                match str::from_utf8(&dbuf) {
-		    Ok(s) => Ok(s.to_string()),
-		    Err(e) => Err(SchemaReadError::DecompressionError)
-		}
-	    },
-	    Err(e) => Err(SchemaReadError::DecompressionError)
-	}
-    } else {
-        static DO_PRINT_UNK_COMP : AtomicBool = AtomicBool::new(false);
-        if DO_PRINT_UNK_COMP.load(Ordering::Relaxed) {
-            println!("read_varstring: unknown compression type {}", co);
-	    DO_PRINT_UNK_COMP.store(true, Ordering::Relaxed);
+        },
+        None => {
+            static DO_PRINT_UNK_COMP : AtomicBool = AtomicBool::new(false);
+            if DO_PRINT_UNK_COMP.load(Ordering::Relaxed) {
+                println!("read_varstring: unknown compression type {}", co);
+                DO_PRINT_UNK_COMP.store(true, Ordering::Relaxed);
+            }
+            Err(SchemaReadError::DecompressionError)
         }
-        Err(SchemaReadError::DecompressionError)
     }
 }
 
-// write variable sized string
-fn write_varstring<B: AppendBuf>(b: &mut B, s: &str) {
-    let buf = Vec::new();
-    let mut compression = 0 as u8;
-
-    // try compressing the string
-    let outbuf = if s.len() < 4096 { // use lz4
-        let mut co = lz4::EncoderBuilder::new()
-            .checksum(lz4::ContentChecksum::NoChecksum)
-            .block_size(lz4::BlockSize::Default)
-            .block_mode(lz4::BlockMode::Linked)
-            .build(buf)
-            .unwrap();
-        let wres = co.write(s.as_bytes());
-        wres.expect("co.write");
-        let (outbuf, fres) = co.finish();
-        fres.expect("co.finish");
-        compression = 'L' as u8;
-        outbuf
-    } else { // use zstd
-        let level = 15;
-        let mut encoder = zstd::stream::Encoder::new(buf, level).unwrap();
-        let wres = encoder.write(s.as_bytes());
-        wres.expect("zstd.write");
-        let outbuf = encoder.finish().unwrap();
-        compression = 'Z' as u8;
-        outbuf
-    };
+// move past a byte sequence written by write_varbytes without reading it
+fn skip_varbytes<B: ReadBuf>(b: &mut B) -> Result<(), SchemaReadError> {
+    read_db(b);
+    let size = read_varint(b);
+    if size > 0 {
+        // reading the last byte tells if the bytes are all there
+        let pos = b.pos();
+        b.seek(pos.saturating_add(size - 1));
+        read_db(b);
+    }
+    if b.past_eof() {
+        return Err(SchemaReadError::UnexpectedEof);
+    }
+    Ok(())
+}
+
+fn read_varstring<B: ReadBuf>(b: &mut B) -> Result<String, SchemaReadError> {
+    read_varstring_with(b, None)
+}
 
-    if outbuf.len() < s.as_bytes().len() {
-        write_db(b, compression); // lz4/zstd mark
-        write_varint(b, outbuf.len());
-        for c in outbuf.as_slice() {
-            write_db(b, *c);
+fn read_varstring_with<B: ReadBuf>(b: &mut B, dictionary: Option<&ZstdDictionary>) -> Result<String, SchemaReadError> {
+    let bytes = read_varbytes_with(b, dictionary)?;
+    // convert bytes to string
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(SchemaReadError::BadUtf8)
+    }
+}
+
+// read a string, an uncompressed one is not copied if the buffer can
+// return its bytes. Then the value is Null and the position and length
+// of the string are returned
+fn read_varstring_span<B: ReadBuf>(b: &mut B, dictionary: Option<&ZstdDictionary>) -> Result<(ColumnValue, Option<(usize, usize)>), SchemaReadError> {
+    let co = read_db(b);
+    let size = read_varint(b);
+    if co == codec::MARKER_NONE {
+        let pos = b.pos();
+        if let Some(bytes) = b.read_slice(size) {
+            return match str::from_utf8(bytes) {
+                Ok(_) => Ok((ColumnValue::Null, Some((pos, size)))),
+                Err(_) => Err(SchemaReadError::BadUtf8),
+            };
         }
-    } else {
-        write_db(b, 0 as u8); // no compression
-        let bytes = s.as_bytes();
-        write_varint(b, bytes.len());
-        for c in bytes {
-            write_db(b, *c);
+    }
+    let bytes = read_sized_bytes(b, co, size, dictionary)?;
+    if b.past_eof() {
+        return Err(SchemaReadError::UnexpectedEof);
+    }
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok((ColumnValue::String { v: s }, None)),
+        Err(_) => Err(SchemaReadError::BadUtf8)
+    }
+}
+
+fn write_varbytes<B: AppendBuf>(b: &mut B, bytes: &[u8], compression: &Compression) {
+    write_varbytes_with(b, bytes, compression, None);
+}
+
+// write variable sized byte sequence, compressed if that makes it smaller
+fn write_varbytes_with<B: AppendBuf>(b: &mut B, bytes: &[u8], compression: &Compression, dictionary: Option<&ZstdDictionary>) {
+    let co = compression.codec(bytes.len());
+
+    // try compressing the bytes, fails for ZstdDict without a dictionary
+    let compressed = match codec::find_codec(codec::marker(&co)) {
+        Some(entry) => (entry.compress)(bytes, &co, dictionary).ok(),
+        None => None,
+    };
+
+    match compressed {
+        Some(ref outbuf) if outbuf.len() < bytes.len() => {
+            write_db(b, codec::marker(&co)); // lz4/zstd/brotli mark
+            write_varint(b, outbuf.len());
+            for c in outbuf.as_slice() {
+                write_db(b, *c);
+            }
+        },
+        _ => {
+            write_db(b, codec::MARKER_NONE); // no compression
+            write_varint(b, bytes.len());
+            for c in bytes {
+                write_db(b, *c);
+            }
         }
     }
 }
 
+// write variable sized string
+fn write_varstring<B: AppendBuf>(b: &mut B, s: &str, compression: &Compression) {
+    write_varbytes_with(b, s.as_bytes(), compression, None);
+}
+
+fn write_varstring_with<B: AppendBuf>(b: &mut B, s: &str, compression: &Compression, dictionary: Option<&ZstdDictionary>) {
+    write_varbytes_with(b, s.as_bytes(), compression, dictionary);
+}
+
 fn flush_buf<B: AppendBuf>(b: &mut B) {
     b.flush();
 }
@@ -247,205 +289,1381 @@ fn read_dq_le<B: ReadBuf>(b: &mut B) -> u64 {
     (b7 as u64) << 56
 }
 
+fn ctype_tag(ct: &ColumnType) -> u8 {
+    match ct {
+        ColumnType::U32le => b'4',
+        ColumnType::U64le => b'8',
+        ColumnType::I32le => b'd',
+        ColumnType::I64le => b'q',
+        ColumnType::F32le => b'f',
+        ColumnType::F64le => b'F',
+        ColumnType::Bool => b'B',
+        ColumnType::String => b'S',
+        ColumnType::Bytes => b'X',
+        ColumnType::Timestamp { .. } => b'T',
+        ColumnType::Decimal { .. } => b'D',
+        ColumnType::FixedBinary { .. } => b'x',
+        ColumnType::List { .. } => b'L',
+    }
+}
+
+// columns that are not plainly encoded have tags of their own,
+// the type parameters are the same as for the plain type
+fn column_tag(ct: &ColumnType, encoding: Encoding) -> u8 {
+    match (encoding, ct) {
+        (Encoding::Dictionary, _) => b'E',
+        (Encoding::Varint, ColumnType::U32le) => b'u',
+        (Encoding::Varint, ColumnType::U64le) => b'U',
+        (Encoding::Varint, ColumnType::I32le) => b'i',
+        (Encoding::Varint, ColumnType::I64le) => b'I',
+        _ => ctype_tag(ct),
+    }
+}
+
+// plain type tag and encoding of a column tag
+fn split_column_tag(tag: u8) -> (u8, Encoding) {
+    match tag {
+        b'E' => (b'S', Encoding::Dictionary),
+        b'u' => (b'4', Encoding::Varint),
+        b'U' => (b'8', Encoding::Varint),
+        b'i' => (b'd', Encoding::Varint),
+        b'I' => (b'q', Encoding::Varint),
+        _ => (tag, Encoding::Plain),
+    }
+}
+
+// parameters of parameterized types, written after the type tag
+// (after the nullable byte for top level columns)
+fn write_ctype_params<B: AppendBuf>(buf: &mut B, ct: &ColumnType) {
+    match ct {
+        ColumnType::Timestamp { unit } => {
+            let u = match unit {
+                TimeUnit::Seconds => b's',
+                TimeUnit::Millis => b'm',
+                TimeUnit::Micros => b'u',
+                TimeUnit::Nanos => b'n',
+            };
+            write_db(buf, u);
+        },
+        ColumnType::Decimal { precision, scale } => {
+            write_db(buf, *precision);
+            write_db(buf, *scale);
+        },
+        ColumnType::FixedBinary { size } => {
+            write_varint(buf, *size);
+        },
+        ColumnType::List { inner } => {
+            write_db(buf, ctype_tag(inner));
+            write_ctype_params(buf, inner);
+        },
+        _ => {}
+    }
+}
+
+// lists of lists deeper than this are rejected so that a corrupt header
+// can not exhaust the stack
+pub const MAX_LIST_DEPTH: usize = 32;
+
+fn read_ctype_params<B: ReadBuf>(buf: &mut B, tag: u8) -> Option<ColumnType> {
+    read_nested_ctype_params(buf, tag, 0)
+}
+
+fn read_nested_ctype_params<B: ReadBuf>(buf: &mut B, tag: u8, depth: usize) -> Option<ColumnType> {
+    let ctype = match tag {
+        b'4' => ColumnType::U32le,
+        b'8' => ColumnType::U64le,
+        b'd' => ColumnType::I32le,
+        b'q' => ColumnType::I64le,
+        b'f' => ColumnType::F32le,
+        b'F' => ColumnType::F64le,
+        b'B' => ColumnType::Bool,
+        b'S' => ColumnType::String,
+        b'X' => ColumnType::Bytes,
+        b'T' => {
+            let unit = match read_db(buf) {
+                b's' => TimeUnit::Seconds,
+                b'm' => TimeUnit::Millis,
+                b'u' => TimeUnit::Micros,
+                b'n' => TimeUnit::Nanos,
+                _ => return None,
+            };
+            ColumnType::Timestamp { unit }
+        },
+        b'x' => {
+            let size = read_varint(buf);
+            if size == 0 {
+                return None;
+            }
+            ColumnType::FixedBinary { size }
+        },
+        b'D' => {
+            let precision = read_db(buf);
+            let scale = read_db(buf);
+            if precision == 0 || precision > decimal::MAX_PRECISION || scale > precision {
+                return None;
+            }
+            ColumnType::Decimal { precision, scale }
+        },
+        b'L' => {
+            if depth >= MAX_LIST_DEPTH {
+                return None;
+            }
+            let inner_tag = read_db(buf);
+            let inner = read_nested_ctype_params(buf, inner_tag, depth + 1)?;
+            ColumnType::List { inner: Box::new(inner) }
+        },
+        _ => {
+            return None; // ERROR TBD
+        }
+    };
+    Some(ctype)
+}
+
+// files start with the magic, the format version and the feature flags,
+// the flags and the columns are covered by an adler32 checksum
+//
+// older files start with '2' followed by the columns only, they can
+// still be read. The header after the magic is version 3
+pub const MAGIC: &[u8] = b"\x89FLATF\r\n";
+pub const FORMAT_VERSION: u8 = 3;
+
+// features a reader has to understand to read the file
+pub const FEATURE_DICTIONARY: u32 = 1; // dictionary encoded string columns
+pub const FEATURE_VARINT: u32 = 2; // varint encoded integer columns
+pub const FEATURE_ZSTD_DICTIONARY: u32 = 4; // zstd dictionary follows the metadata
+pub const FEATURE_BLOCKS: u32 = 8; // rows are stored in compressed blocks
+pub const FEATURE_CHECKSUM: u32 = 16; // row checksum other than adler32 per group follows
+pub const FEATURE_SYNC: u32 = 32; // sync marker follows, sync records between the rows
+pub const FEATURE_INDEX: u32 = 64; // the writer adds a footer with an index when closed
+pub const FEATURE_COLUMNAR: u32 = 128; // blocks are row groups stored column by column
+const KNOWN_FEATURES: u32 = FEATURE_DICTIONARY | FEATURE_VARINT | FEATURE_ZSTD_DICTIONARY |
+    FEATURE_BLOCKS | FEATURE_CHECKSUM | FEATURE_SYNC | FEATURE_INDEX | FEATURE_COLUMNAR;
+
+fn checksum_tag(checksum: Checksum) -> u8 {
+    match checksum {
+        Checksum::None => b'n',
+        Checksum::Adler32 => b'a',
+        Checksum::Crc32c => b'c',
+        Checksum::XxHash32 => b'x',
+    }
+}
+
+fn read_checksum<B: ReadBuf>(buf: &mut B) -> Option<(Checksum, ChecksumGranularity)> {
+    let checksum = match read_db(buf) {
+        b'n' => Checksum::None,
+        b'a' => Checksum::Adler32,
+        b'c' => Checksum::Crc32c,
+        b'x' => Checksum::XxHash32,
+        _ => return None,
+    };
+    let granularity = match read_db(buf) {
+        b'G' => ChecksumGranularity::Group,
+        b'R' => ChecksumGranularity::Row,
+        _ => return None,
+    };
+    Some((checksum, granularity))
+}
+
+fn write_checksum<B: AppendBuf>(buf: &mut B, checksum: Checksum, granularity: ChecksumGranularity) {
+    write_db(buf, checksum_tag(checksum));
+    write_db(buf, match granularity {
+        ChecksumGranularity::Group => b'G',
+        ChecksumGranularity::Row => b'R',
+    });
+}
+
+fn default_checksum(schema: &Schema2) -> bool {
+    schema.checksum == Checksum::Adler32 && schema.checksum_granularity == ChecksumGranularity::Group
+}
+
+fn schema_features(schema: &Schema2) -> u32 {
+    let mut features = 0;
+    for e in &schema.encodings {
+        features |= match e {
+            Encoding::Plain => 0,
+            Encoding::Dictionary => FEATURE_DICTIONARY,
+            Encoding::Varint => FEATURE_VARINT,
+        };
+    }
+    if schema.zstd_dictionary().is_some() {
+        features |= FEATURE_ZSTD_DICTIONARY;
+    }
+    if schema.blocks.is_some() {
+        features |= FEATURE_BLOCKS;
+        if schema.columnar {
+            features |= FEATURE_COLUMNAR;
+        }
+    }
+    if !default_checksum(schema) {
+        features |= FEATURE_CHECKSUM;
+    }
+    if schema.sync.is_some() {
+        features |= FEATURE_SYNC;
+    }
+    if schema.index_rows.is_some() {
+        features |= FEATURE_INDEX;
+    }
+    features
+}
+
 pub fn read_schema_v2<B: ReadBuf>(
   buf: &mut B,
   ) -> Option<Schema2> {
     let version = read_db(buf);
-    if version == '2' as u8 {
-        let mut schema = Schema2::new();
-        let num_columns = read_varint(buf);
-        for i in 0..num_columns {
-            let vs = read_varstring(buf);
-            let s = match vs {
+    if version == b'2' {
+        return read_schema_columns(buf, false);
+    }
+    if version != MAGIC[0] {
+        return None;
+    }
+    for m in &MAGIC[1..] {
+        if read_db(buf) != *m {
+            return None;
+        }
+    }
+    if read_db(buf) > FORMAT_VERSION {
+        return None; // written by a newer version
+    }
+
+    let (schema, hash) = {
+        let mut checkbuf = ReadBufChecksum::new(buf, Checksum::Adler32);
+        let features = read_dd_le(&mut checkbuf);
+        if features & !KNOWN_FEATURES != 0 {
+            return None;
+        }
+        let mut schema = read_schema_columns(&mut checkbuf, true);
+        if features & FEATURE_ZSTD_DICTIONARY != 0 {
+            let dictionary = read_varbytes(&mut checkbuf).ok();
+            match schema {
+                Some(ref mut s) if dictionary.is_some() => s.set_zstd_dictionary(dictionary),
+                _ => return None,
+            }
+        }
+        if features & FEATURE_BLOCKS != 0 {
+            if let Some(ref mut s) = schema {
+                s.set_blocks(Some(BlockOptions::new()));
+            }
+        }
+        if features & FEATURE_COLUMNAR != 0 {
+            match schema {
+                Some(ref mut s) if features & FEATURE_BLOCKS != 0 => s.set_columnar(true),
+                _ => return None,
+            }
+        }
+        if features & FEATURE_CHECKSUM != 0 {
+            let checksum = read_checksum(&mut checkbuf);
+            match (&mut schema, checksum) {
+                (Some(ref mut s), Some((c, g))) => s.set_checksum(c, g),
+                _ => return None,
+            }
+        }
+        if features & FEATURE_SYNC != 0 {
+            let mut sync = SyncOptions { marker: [0; SYNC_MARKER_LEN], interval: DEFAULT_SYNC_INTERVAL };
+            for m in sync.marker.iter_mut() {
+                *m = read_db(&mut checkbuf);
+            }
+            if let Some(ref mut s) = schema {
+                s.set_sync(Some(sync));
+            }
+        }
+        if features & FEATURE_INDEX != 0 {
+            if let Some(ref mut s) = schema {
+                s.set_index(Some(DEFAULT_INDEX_ROWS));
+            }
+        }
+        (schema, checkbuf.hash().unwrap())
+    };
+    let fhash = read_dd_le(buf);
+    if buf.past_eof() || hash != fhash {
+        return None;
+    }
+    schema
+}
+
+fn read_schema_columns<B: ReadBuf>(
+  buf: &mut B,
+  extended: bool,
+  ) -> Option<Schema2> {
+    let mut schema = Schema2::new();
+    let num_columns = read_varint(buf);
+    for i in 0..num_columns {
+        let vs = read_varstring(buf);
+        let s = match vs {
+            Ok(x) => x,
+            Err(e) => return None
+        };
+        let ct = read_db(buf);
+        let n = read_db(buf);
+        let (ct, encoding) = split_column_tag(ct);
+        let ctype = read_ctype_params(buf, ct)?;
+        let nullable = n == b'N';
+        let is_timestamp = if let ColumnType::Timestamp { .. } = ctype { true } else { false };
+        schema.add(s.as_str(), ctype, nullable);
+        schema.set_encoding(i, encoding);
+
+        if is_timestamp {
+            // timezone, empty when not specified
+            let tz = match read_varstring(buf) {
                 Ok(x) => x,
                 Err(e) => return None
             };
-            let ct = read_db(buf);
-            let n = read_db(buf);
-            let ctype = match ct {
-                b'4' => ColumnType::U32le,
-                b'8' => ColumnType::U64le,
-                b'S' => ColumnType::String,
-                _ => {
-                    return None; // ERROR TBD
-                }
-            };
-            let nullable = n == 'N' as u8;
-            schema.add(s.as_str(), ctype, nullable);
+            if tz.len() > 0 {
+                schema.set_timezone(i, Some(tz.as_str()));
+            }
+        }
+
+        if extended {
+            // description, empty when not specified
+            let d = read_varstring(buf).ok()?;
+            if d.len() > 0 {
+                schema.set_description(i, Some(d.as_str()));
+            }
         }
-        return Some(schema)
     }
-    None
+
+    if extended {
+        let num_metadata = read_varint(buf);
+        for _ in 0..num_metadata {
+            let key = read_varstring(buf).ok()?;
+            let value = read_varstring(buf).ok()?;
+            schema.set_metadata(&key, &value);
+        }
+    }
+    Some(schema)
 }
 
 pub fn write_schema_v2<B: AppendBuf>(
   buf: &mut B,
   schema: &Schema2,
   ) {
-    write_db(buf, '2' as u8); // version 2
+    for m in MAGIC {
+        write_db(buf, *m);
+    }
+    write_db(buf, FORMAT_VERSION);
+
+    let hash = {
+        let mut checkbuf = AppendBufChecksum::new(buf, Checksum::Adler32);
+        write_dd_le(&mut checkbuf, schema_features(schema));
+        write_schema_columns(&mut checkbuf, schema, true);
+        if let Some(dictionary) = schema.zstd_dictionary() {
+            write_varbytes(&mut checkbuf, dictionary, &Compression::none());
+        }
+        if !default_checksum(schema) {
+            write_checksum(&mut checkbuf, schema.checksum, schema.checksum_granularity);
+        }
+        if let Some(sync) = schema.sync {
+            for m in sync.marker.iter() {
+                write_db(&mut checkbuf, *m);
+            }
+        }
+        checkbuf.hash().unwrap()
+    };
+    write_dd_le(buf, hash);
+}
+
+fn write_schema_columns<B: AppendBuf>(
+  buf: &mut B,
+  schema: &Schema2,
+  extended: bool,
+  ) {
     write_varint(buf, schema.names.len());
 
     for colidx in 0..schema.names.len() {
         // column name
-        write_varstring(buf, schema.names[colidx].as_str());
+        write_varstring(buf, schema.names[colidx].as_str(), &Compression::new());
 
-        let ct = match schema.types[colidx] {
-            ColumnType::U32le => '4' as u8,
-            ColumnType::U64le => '8' as u8,
-            ColumnType::String => 'S' as u8,
-        };
-        write_db(buf, ct);
+        write_db(buf, column_tag(&schema.types[colidx], schema.encodings[colidx]));
         if schema.nullable[colidx] {
             write_db(buf, 'N' as u8);
         } else {
             write_db(buf, 0 as u8);
         }
+
+        // type parameters follow the nullable byte
+        write_ctype_params(buf, &schema.types[colidx]);
+        if let ColumnType::Timestamp { .. } = schema.types[colidx] {
+            write_varstring(buf, schema.timezone(colidx).unwrap_or(""), &Compression::new());
+        }
+
+        if extended {
+            write_varstring(buf, schema.description(colidx).unwrap_or(""), &Compression::new());
+        }
+    }
+
+    if extended {
+        write_varint(buf, schema.metadata.len());
+        for (key, value) in &schema.metadata {
+            write_varstring(buf, key, &Compression::new());
+            write_varstring(buf, value, &Compression::new());
+        }
     }
 }
 
+// true if a non-null value can be stored in a column of type ct
+fn value_matches(ct: &ColumnType, value: &ColumnValue) -> bool {
+    match (ct, value) {
+        (ColumnType::U32le, ColumnValue::U32 { .. }) => true,
+        (ColumnType::U64le, ColumnValue::U64 { .. }) => true,
+        (ColumnType::I32le, ColumnValue::I32 { .. }) => true,
+        (ColumnType::I64le, ColumnValue::I64 { .. }) => true,
+        (ColumnType::F32le, ColumnValue::F32 { .. }) => true,
+        (ColumnType::F64le, ColumnValue::F64 { .. }) => true,
+        (ColumnType::Bool, ColumnValue::Bool { .. }) => true,
+        (ColumnType::String, ColumnValue::String { .. }) => true,
+        (ColumnType::Bytes, ColumnValue::Bytes { .. }) => true,
+        (ColumnType::Timestamp { unit }, ColumnValue::Timestamp { unit: vunit, .. }) => {
+            unit == vunit
+        },
+        (ColumnType::Decimal { precision, scale }, ColumnValue::Decimal { v, scale: vscale }) => {
+            scale == vscale && decimal::fits(*v, *precision)
+        },
+        (ColumnType::FixedBinary { size }, ColumnValue::Bytes { v }) => v.len() == *size,
+        (ColumnType::List { inner }, ColumnValue::List { v }) => {
+            // list elements can not be null
+            v.iter().all(|e| value_matches(inner, e))
+        },
+        _ => false,
+    }
+}
+
+// files with blocks, sync records or an index are more than a sequence of
+// rows, they have to be written with a Writer and read with a RowReader
+fn needs_writer(schema: &Schema2) -> bool {
+    schema.blocks.is_some() || schema.sync.is_some() || schema.index_rows.is_some()
+}
+
+fn has_dictionary(schema: &Schema2) -> bool {
+    schema.encodings.contains(&Encoding::Dictionary)
+}
 
+// write a row of a file without dictionary encoded columns, false for
+// schemas that need a Writer
 pub fn schema_write<B: AppendBuf>(
+    buf: &mut B,
+    values: &[ColumnValue],
+    schema: &Schema2,
+) -> bool {
+    if has_dictionary(schema) {
+        return false;
+    }
+    schema_write_with(buf, values, schema, &mut Dictionaries::new(schema), &WriteOptions::new())
+}
+
+// write a row, 'dicts' keeps the dictionaries between the rows of a file.
+// false for schemas that need a Writer
+pub fn schema_write_with<B: AppendBuf>(
+    buf: &mut B,
+    values: &[ColumnValue],
+    schema: &Schema2,
+    dicts: &mut Dictionaries,
+    options: &WriteOptions,
+) -> bool {
+    if needs_writer(schema) {
+        return false;
+    }
+    write_row(buf, values, schema, dicts, options)
+}
+
+// write a row without blocks or sync records around it, for the Writer
+pub(crate) fn write_row<B: AppendBuf>(
     mut buf: &mut B,
     values: &[ColumnValue],
     schema: &Schema2,
+    dicts: &mut Dictionaries,
+    options: &WriteOptions,
 ) -> bool {
+    if !row_matches(values, schema) {
+        return false;
+    }
+    schema_write_row::<B>(&mut buf, &values, schema, dicts, options);
+    true
+}
+
+// true if the values can be written as a row of the schema
+pub fn row_matches(values: &[ColumnValue], schema: &Schema2) -> bool {
     assert!(values.len() == schema.types.len());
 
     for i in 0..values.len() {
-        if !schema.nullable[i] && values[i] == ColumnValue::Null {
+        if values[i] == ColumnValue::Null {
+            if !schema.nullable[i] {
+                return false;
+            }
+        } else if !value_matches(&schema.types[i], &values[i]) {
             return false;
         }
-        match (schema.types[i], &values[i]) {
-            (ColumnType::U32le, &ColumnValue::U32 { v }) => {}
-            (ColumnType::U32le, &ColumnValue::Null) => {},
-            (ColumnType::U64le, &ColumnValue::U64 { v }) => {}
-            (ColumnType::U64le, &ColumnValue::Null) => {},
-            (ColumnType::String, &ColumnValue::String { ref v }) => {}
-            (ColumnType::String, &ColumnValue::Null) => {},
-             _ => {
-                 return false
-             }
+    }
+    true
+}
+
+// true if the group of 8 columns starting at 'first' has a bool column,
+// in which case a byte with the packed bool values follows the null byte
+fn has_bool_byte(schema: &Schema2, first: usize) -> bool {
+    let last = min(first + 8, schema.len());
+    schema.types[first..last].iter().any(|t| *t == ColumnType::Bool)
+}
+
+// read a single non-null value of type ct
+fn read_value<B: ReadBuf>(b: &mut B, ct: &ColumnType, dictionary: Option<&ZstdDictionary>) -> Result<ColumnValue, SchemaReadError> {
+    let value = match ct {
+        ColumnType::U32le => ColumnValue::U32 { v: read_dd_le(b) },
+        ColumnType::U64le => ColumnValue::U64 { v: read_dq_le(b) },
+        ColumnType::I32le => ColumnValue::I32 { v: read_dd_le(b) as i32 },
+        ColumnType::I64le => ColumnValue::I64 { v: read_dq_le(b) as i64 },
+        ColumnType::F32le => ColumnValue::F32 { v: f32::from_bits(read_dd_le(b)) },
+        ColumnType::F64le => ColumnValue::F64 { v: f64::from_bits(read_dq_le(b)) },
+        ColumnType::Bool => ColumnValue::Bool { v: read_db(b) != 0 }, // only inside lists
+        ColumnType::Timestamp { unit } => {
+            ColumnValue::Timestamp { v: read_dq_le(b) as i64, unit: *unit }
+        },
+        ColumnType::Decimal { precision, scale } => {
+            let v = if *precision <= decimal::MAX_PRECISION_64 {
+                read_dq_le(b) as i64 as i128
+            } else {
+                let lo = read_dq_le(b);
+                let hi = read_dq_le(b);
+                ((hi as i128) << 64) | (lo as i128)
+            };
+            ColumnValue::Decimal { v, scale: *scale }
+        },
+        ColumnType::String => ColumnValue::String { v: read_varstring_with(b, dictionary)? },
+        ColumnType::Bytes => ColumnValue::Bytes { v: read_varbytes_with(b, dictionary)? },
+        ColumnType::FixedBinary { size } => {
+            // raw bytes, no length or compression marker
+            let mut bytes = Vec::with_capacity(*size);
+            for _ in 0..*size {
+                bytes.push(read_db(b));
+            }
+            ColumnValue::Bytes { v: bytes }
+        },
+        ColumnType::List { inner } => {
+            let count = read_varint(b);
+            let mut elements = Vec::new();
+            for _ in 0..count {
+                if b.past_eof() {
+                    return Result::Err(SchemaReadError::UnexpectedEof);
+                }
+                elements.push(read_value(b, inner, dictionary)?);
+            }
+            ColumnValue::List { v: elements }
+        },
+    };
+    if b.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok(value)
+}
+
+// move past a single non-null value of type ct without reading it
+fn skip_value<B: ReadBuf>(b: &mut B, ct: &ColumnType) -> Result<(), SchemaReadError> {
+    let size = match ct {
+        ColumnType::U32le | ColumnType::I32le | ColumnType::F32le => 4,
+        ColumnType::U64le | ColumnType::I64le | ColumnType::F64le | ColumnType::Timestamp { .. } => 8,
+        ColumnType::Bool => 1, // only inside lists
+        ColumnType::Decimal { precision, .. } => if *precision <= decimal::MAX_PRECISION_64 { 8 } else { 16 },
+        ColumnType::FixedBinary { size } => *size,
+        ColumnType::String | ColumnType::Bytes => return skip_varbytes(b),
+        ColumnType::List { inner } => {
+            let count = read_varint(b);
+            for _ in 0..count {
+                if b.past_eof() {
+                    return Result::Err(SchemaReadError::UnexpectedEof);
+                }
+                skip_value(b, inner)?;
+            }
+            0
+        },
+    };
+    if size > 0 {
+        // reading the last byte tells if the value is all there
+        let pos = b.pos();
+        b.seek(pos + size - 1);
+        read_db(b);
+    }
+    if b.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok(())
+}
+
+// write a single non-null value of type ct
+fn write_value<B: AppendBuf>(b: &mut B, ct: &ColumnType, value: &ColumnValue, compression: &Compression, dictionary: Option<&ZstdDictionary>) {
+    match (ct, value) {
+        (_, ColumnValue::U32 { v }) => write_dd_le(b, *v),
+        (_, ColumnValue::U64 { v }) => write_dq_le(b, *v),
+        (_, ColumnValue::I32 { v }) => write_dd_le(b, *v as u32),
+        (_, ColumnValue::I64 { v }) => write_dq_le(b, *v as u64),
+        (_, ColumnValue::F32 { v }) => write_dd_le(b, v.to_bits()),
+        (_, ColumnValue::F64 { v }) => write_dq_le(b, v.to_bits()),
+        (_, ColumnValue::Bool { v }) => write_db(b, *v as u8), // only inside lists
+        (_, ColumnValue::Timestamp { v, .. }) => write_dq_le(b, *v as u64),
+        (ColumnType::Decimal { precision, .. }, ColumnValue::Decimal { v, .. }) => {
+            if *precision > decimal::MAX_PRECISION_64 {
+                write_dq_le(b, *v as u64);
+                write_dq_le(b, (*v >> 64) as u64);
+            } else {
+                write_dq_le(b, *v as u64);
+            }
+        },
+        (_, ColumnValue::String { v }) => write_varstring_with(b, v, compression, dictionary),
+        (ColumnType::FixedBinary { .. }, ColumnValue::Bytes { v }) => {
+            for c in v {
+                write_db(b, *c);
+            }
+        },
+        (_, ColumnValue::Bytes { v }) => write_varbytes_with(b, v, compression, dictionary),
+        (ColumnType::List { inner }, ColumnValue::List { v }) => {
+            write_varint(b, v.len());
+            for e in v {
+                write_value(b, inner, e, compression, dictionary);
+            }
+        },
+        _ => panic!("write_value: value {:?} does not match type {:?}", value, ct),
+    }
+}
+
+// read a string of a dictionary encoded column
+fn read_dict_value<B: ReadBuf>(b: &mut B, dicts: &mut Dictionaries, index: usize, dictionary: Option<&ZstdDictionary>) -> Result<ColumnValue, SchemaReadError> {
+    let code = read_varint(b);
+    let dict = dicts.column(index);
+    let s = if code < DICT_CODE_FIRST_INDEX {
+        let s = read_varstring_with(b, dictionary)?;
+        dict.push(code, &s);
+        s
+    } else {
+        match dict.get(code) {
+            Some(s) => s.clone(),
+            None => return Result::Err(SchemaReadError::BadDictionaryIndex),
         }
+    };
+    if b.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
     }
+    Result::Ok(ColumnValue::String { v: s })
+}
 
-    schema_write_row::<B>(&mut buf, &values);
-    true
+// like read_dict_value, only the strings new to the dictionary are read
+fn skip_dict_value<B: ReadBuf>(b: &mut B, dicts: &mut Dictionaries, index: usize, dictionary: Option<&ZstdDictionary>) -> Result<(), SchemaReadError> {
+    let code = read_varint(b);
+    let dict = dicts.column(index);
+    if code < DICT_CODE_FIRST_INDEX {
+        let s = read_varstring_with(b, dictionary)?;
+        dict.push(code, &s);
+    } else if dict.get(code).is_none() {
+        return Result::Err(SchemaReadError::BadDictionaryIndex);
+    }
+    if b.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok(())
+}
+
+// write a string of a dictionary encoded column
+fn write_dict_value<B: AppendBuf>(b: &mut B, dicts: &mut Dictionaries, index: usize, s: &str, compression: &Compression, dictionary: Option<&ZstdDictionary>) {
+    let code = dicts.column(index).encode(s);
+    write_varint(b, code);
+    if code < DICT_CODE_FIRST_INDEX {
+        write_varstring_with(b, s, compression, dictionary);
+    }
+}
+
+// read an integer of a varint encoded column
+fn read_varint_value<B: ReadBuf>(b: &mut B, ct: &ColumnType) -> Result<ColumnValue, SchemaReadError> {
+    let u = read_varint64(b);
+    let value = match ct {
+        ColumnType::U32le => ColumnValue::U32 { v: u as u32 },
+        ColumnType::U64le => ColumnValue::U64 { v: u },
+        ColumnType::I32le => ColumnValue::I32 { v: unzigzag(u) as i32 },
+        ColumnType::I64le => ColumnValue::I64 { v: unzigzag(u) },
+        _ => panic!("read_varint_value: {:?} is not an integer type", ct),
+    };
+    if b.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok(value)
+}
+
+// write an integer of a varint encoded column
+fn write_varint_value<B: AppendBuf>(b: &mut B, value: &ColumnValue) {
+    let u = match value {
+        ColumnValue::U32 { v } => *v as u64,
+        ColumnValue::U64 { v } => *v,
+        ColumnValue::I32 { v } => zigzag(*v as i64),
+        ColumnValue::I64 { v } => zigzag(*v),
+        _ => panic!("write_varint_value: {:?} is not an integer", value),
+    };
+    write_varint64(b, u);
+}
+
+// map signed integers to unsigned so that small negative numbers
+// have short varints: 0, -1, 1, -2, 2 -> 0, 1, 2, 3, 4
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(u: u64) -> i64 {
+    ((u >> 1) as i64) ^ -((u & 1) as i64)
+}
+
+// a block is the number of rows followed by the rows written by
+// schema_write_with, compressed as a single value
+pub fn write_block<B: AppendBuf>(buf: &mut B, nrows: usize, rows: &[u8], codec: &Codec, dictionary: Option<&ZstdDictionary>) {
+    write_varint(buf, nrows);
+    write_varbytes_with(buf, rows, &Compression::single(*codec), dictionary);
+}
+
+// returns the number of rows and the rows of the next block
+pub fn read_block<B: ReadBuf>(buf: &mut B, dictionary: Option<&ZstdDictionary>) -> Result<(usize, Vec<u8>), SchemaReadError> {
+    let nrows = read_varint(buf);
+    if buf.past_eof() {
+        return Result::Err(SchemaReadError::Eof);
+    }
+    let rows = read_varbytes_with(buf, dictionary)?;
+    if buf.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok((nrows, rows))
+}
+
+// a row group is the number of rows followed by a chunk for every column,
+// compressed as a single value. A chunk has the null bits of the rows if
+// the column is nullable, the packed values for bool columns, and else
+// the values that are not null. Dictionaries start empty in every chunk
+pub fn write_row_group<B: AppendBuf>(
+    buf: &mut B,
+    rows: &[Vec<ColumnValue>],
+    schema: &Schema2,
+    options: &WriteOptions,
+    codec: &Codec,
+) {
+    write_varint(buf, rows.len());
+    let mut dicts = Dictionaries::new(schema);
+    for col in 0..schema.len() {
+        let mut chunk: Vec<u8> = Vec::new();
+        {
+            let mut checkbuf = AppendBufChecksum::new(&mut chunk, schema.checksum);
+            if schema.nullable[col] {
+                write_bits(&mut checkbuf, rows.iter().map(|r| r[col] == ColumnValue::Null));
+            }
+            if schema.types[col] == ColumnType::Bool {
+                write_bits(&mut checkbuf, rows.iter().map(|r| r[col] == ColumnValue::Bool { v: true }));
+            } else {
+                let compression = schema.compression(col).unwrap_or(options.compression);
+                for row in rows {
+                    match (schema.encodings[col], &row[col]) {
+                        (_, ColumnValue::Null) => {},
+                        (Encoding::Dictionary, ColumnValue::String { v }) => {
+                            write_dict_value(&mut checkbuf, &mut dicts, col, v, &compression, schema.prepared_zstd_dictionary());
+                        },
+                        (Encoding::Varint, v) => write_varint_value(&mut checkbuf, v),
+                        (_, v) => write_value(&mut checkbuf, &schema.types[col], v, &compression, schema.prepared_zstd_dictionary()),
+                    }
+                }
+            }
+            if let Some(hash) = checkbuf.hash() {
+                write_dd_le(checkbuf.get_mut(), hash);
+            }
+        }
+        write_varbytes_with(buf, &chunk, &Compression::single(*codec), schema.prepared_zstd_dictionary());
+    }
+}
+
+fn write_bits<B: AppendBuf, I: Iterator<Item = bool>>(buf: &mut B, bits: I) {
+    let mut byte = 0u8;
+    let mut n = 0;
+    for bit in bits {
+        if bit {
+            byte |= 1 << (n % 8);
+        }
+        n += 1;
+        if n % 8 == 0 {
+            write_db(buf, byte);
+            byte = 0;
+        }
+    }
+    if n % 8 != 0 {
+        write_db(buf, byte);
+    }
+}
+
+fn read_bits<B: ReadBuf>(buf: &mut B, n: usize) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(n);
+    let mut byte = 0;
+    for i in 0..n {
+        if i % 8 == 0 {
+            byte = read_db(buf);
+        }
+        bits.push(byte & (1 << (i % 8)) != 0);
+    }
+    bits
+}
+
+// reads a row group into 'columns', one vector of values per column.
+// The chunks of the columns that are not 'needed' are skipped without
+// decompressing them and their vectors are left empty
+pub fn read_row_group<B: ReadBuf>(
+    buf: &mut B,
+    schema: &Schema2,
+    needed: &[bool],
+    columns: &mut Vec<Vec<ColumnValue>>,
+) -> Result<usize, SchemaReadError> {
+    let nrows = read_varint(buf);
+    if buf.past_eof() {
+        return Result::Err(SchemaReadError::Eof);
+    }
+    // the whole group is read before returning an error in a chunk, so
+    // that the next read starts at the next group
+    let mut chunks = Vec::new();
+    for need in needed.iter().take(schema.len()) {
+        if *need {
+            match read_varbytes_with(buf, schema.prepared_zstd_dictionary()) {
+                Err(SchemaReadError::UnexpectedEof) => return Result::Err(SchemaReadError::UnexpectedEof),
+                r => chunks.push(Some(r)),
+            }
+        } else {
+            skip_varbytes(buf)?;
+            chunks.push(None);
+        }
+    }
+    columns.clear();
+    let mut dicts = Dictionaries::new(schema);
+    for (col, chunk) in chunks.into_iter().enumerate() {
+        let values = match chunk {
+            Some(chunk) => read_chunk(chunk?, nrows, col, schema, &mut dicts)?,
+            None => Vec::new(),
+        };
+        columns.push(values);
+    }
+    Result::Ok(nrows)
+}
+
+fn read_chunk(
+    chunk: Vec<u8>,
+    nrows: usize,
+    col: usize,
+    schema: &Schema2,
+    dicts: &mut Dictionaries,
+) -> Result<Vec<ColumnValue>, SchemaReadError> {
+    let mut vb = Vecbuf::from_vec(chunk);
+    let mut checkbuf = ReadBufChecksum::new(&mut vb, schema.checksum);
+    let nulls = if schema.nullable[col] { read_bits(&mut checkbuf, nrows) } else { vec![false; nrows] };
+    let ct = &schema.types[col];
+    let mut values = Vec::with_capacity(nrows);
+    if *ct == ColumnType::Bool {
+        for (null, v) in nulls.iter().zip(read_bits(&mut checkbuf, nrows)) {
+            values.push(if *null { ColumnValue::Null } else { ColumnValue::Bool { v } });
+        }
+    } else {
+        for null in nulls {
+            if null {
+                values.push(ColumnValue::Null);
+                continue;
+            }
+            if checkbuf.past_eof() {
+                return Result::Err(SchemaReadError::UnexpectedEof);
+            }
+            values.push(match schema.encodings[col] {
+                Encoding::Plain => read_value(&mut checkbuf, ct, schema.prepared_zstd_dictionary())?,
+                Encoding::Dictionary => read_dict_value(&mut checkbuf, dicts, col, schema.prepared_zstd_dictionary())?,
+                Encoding::Varint => read_varint_value(&mut checkbuf, ct)?,
+            });
+        }
+    }
+    if let Some(hash) = checkbuf.hash() {
+        let fhash = read_dd_le(checkbuf.get_mut());
+        if hash != fhash {
+            return Result::Err(SchemaReadError::ChecksumError);
+        }
+    }
+    if checkbuf.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
+    Result::Ok(values)
+}
+
+// a sync record is the sync marker, the offset of the record in the file
+// and the number of rows since the previous record
+pub fn write_sync<B: AppendBuf>(buf: &mut B, sync: &SyncOptions, offset: usize, nrows: usize) {
+    for m in sync.marker.iter() {
+        write_db(buf, *m);
+    }
+    write_dq_le(buf, offset as u64);
+    write_varint(buf, nrows);
+}
+
+// reads the sync record at the current position and returns its number
+// of rows, the position is left unchanged if there is none
+pub fn read_sync<B: ReadBuf>(buf: &mut B, sync: &SyncOptions) -> Option<usize> {
+    let pos = buf.pos();
+    for m in sync.marker.iter() {
+        if read_db(buf) != *m {
+            buf.seek(pos);
+            return None;
+        }
+    }
+    // the offset rules out a copy of the marker inside a value
+    let offset = read_dq_le(buf);
+    let nrows = read_varint(buf);
+    if buf.past_eof() || offset != pos as u64 {
+        buf.seek(pos);
+        return None;
+    }
+    Some(nrows)
+}
+
+// bytes searched at a time for the first byte of a sync marker
+const SCAN_CHUNK: usize = 1 << 16;
+
+// the position of the first 'byte' at or after 'from', searching the
+// slices of the buffer, or reading byte by byte if it has none
+fn scan_byte<B: ReadBuf>(buf: &mut B, byte: u8, from: usize) -> Option<usize> {
+    let mut pos = from;
+    loop {
+        if buf.seek(pos) != pos || buf.past_eof() {
+            return None;
+        }
+        let found = buf.read_slice(SCAN_CHUNK).map(|s| s.iter().position(|b| *b == byte));
+        match found {
+            Some(Some(i)) => return Some(pos + i),
+            Some(None) => pos += SCAN_CHUNK,
+            None => {
+                // less than a chunk left, or no slices
+                buf.seek(pos);
+                loop {
+                    let b = buf.readb();
+                    if buf.past_eof() {
+                        return None;
+                    }
+                    if b == byte {
+                        return Some(pos);
+                    }
+                    pos += 1;
+                }
+            },
+        }
+    }
+}
+
+// the position of the last 'byte' at or after 'start' and before 'before'
+fn rscan_byte<B: ReadBuf>(buf: &mut B, byte: u8, start: usize, before: usize) -> Option<usize> {
+    let mut end = before;
+    while end > start {
+        let lo = if end - start > SCAN_CHUNK { end - SCAN_CHUNK } else { start };
+        let found = if buf.seek(lo) == lo {
+            buf.read_slice(end - lo).map(|s| s.iter().rposition(|b| *b == byte))
+        } else {
+            None
+        };
+        match found {
+            Some(Some(i)) => return Some(lo + i),
+            Some(None) => {},
+            None => {
+                for pos in (lo..end).rev() {
+                    if buf.seek(pos) == pos && buf.readb() == byte && !buf.past_eof() {
+                        return Some(pos);
+                    }
+                }
+            },
+        }
+        end = lo;
+    }
+    None
+}
+
+// skips to the first sync record at or after 'from' and reads it,
+// None if there is none before the end of the file
+pub fn find_sync<B: ReadBuf>(buf: &mut B, sync: &SyncOptions, from: usize) -> Option<usize> {
+    let mut pos = from;
+    loop {
+        pos = scan_byte(buf, sync.marker[0], pos)?;
+        buf.seek(pos);
+        if let Some(nrows) = read_sync(buf, sync) {
+            return Some(nrows);
+        }
+        pos += 1;
+    }
+}
+
+// the position of the last sync record that starts at or after 'start'
+// and before 'before', searching backwards from the end
+pub fn rfind_sync<B: ReadBuf>(buf: &mut B, sync: &SyncOptions, start: usize, before: usize) -> Option<usize> {
+    let mut before = before;
+    loop {
+        let pos = rscan_byte(buf, sync.marker[0], start, before)?;
+        buf.seek(pos);
+        if read_sync(buf, sync).is_some() {
+            return Some(pos);
+        }
+        before = pos;
+    }
+}
+
+// the footer is the index, an adler32 of it, the size of the index
+// and FOOTER_MAGIC. it is missing when the writer did not finish
+pub const FOOTER_MAGIC: &[u8] = b"FIDX";
+const FOOTER_TRAILER_LEN: usize = 12;
+const FOOTER_BLOOM: u8 = b'B';
+
+fn write_stats_value<B: AppendBuf>(buf: &mut B, ct: &ColumnType, value: &ColumnValue) {
+    if *value == ColumnValue::Null {
+        write_db(buf, 0);
+    } else {
+        write_db(buf, 1);
+        write_value(buf, ct, value, &Compression::none(), None);
+    }
+}
+
+fn read_stats_value<B: ReadBuf>(buf: &mut B, ct: &ColumnType) -> Option<ColumnValue> {
+    if read_db(buf) == 0 {
+        Some(ColumnValue::Null)
+    } else {
+        read_value(buf, ct, None).ok()
+    }
+}
+
+pub fn write_footer<B: AppendBuf>(buf: &mut B, schema: &Schema2, index: &FileIndex) {
+    let mut body: Vec<u8> = Vec::new();
+    write_varint(&mut body, index.blocks.len());
+    for block in &index.blocks {
+        write_varint(&mut body, block.offset);
+        write_varint(&mut body, block.rows);
+        for (ct, stats) in schema.types.iter().zip(block.columns.iter()) {
+            write_varint(&mut body, stats.nulls);
+            write_stats_value(&mut body, ct, &stats.min);
+            write_stats_value(&mut body, ct, &stats.max);
+        }
+    }
+    // the Bloom filters follow the statistics of all blocks, so that
+    // footers without them stay the same
+    if index.blocks.iter().any(|b| b.blooms.iter().any(|f| f.is_some())) {
+        write_db(&mut body, FOOTER_BLOOM);
+        for block in &index.blocks {
+            for bloom in &block.blooms {
+                match bloom {
+                    Some(bloom) => {
+                        write_varint(&mut body, bloom.hashes);
+                        write_varint(&mut body, bloom.bits.len());
+                        for b in &bloom.bits {
+                            write_db(&mut body, *b);
+                        }
+                    },
+                    None => write_varint(&mut body, 0),
+                }
+            }
+        }
+    }
+    let hash = {
+        let mut checkbuf = AppendBufChecksum::new(buf, Checksum::Adler32);
+        for b in &body {
+            write_db(&mut checkbuf, *b);
+        }
+        checkbuf.hash().unwrap()
+    };
+    write_dd_le(buf, hash);
+    write_dd_le(buf, body.len() as u32);
+    for m in FOOTER_MAGIC {
+        write_db(buf, *m);
+    }
 }
 
+// reads the footer at the end of a file of 'len' bytes, returns the index
+// and the offset of the footer, where the rows end
+pub fn read_footer<B: ReadBuf>(buf: &mut B, schema: &Schema2, len: usize) -> Option<(FileIndex, usize)> {
+    if len < FOOTER_TRAILER_LEN {
+        return None;
+    }
+    buf.seek(len - FOOTER_MAGIC.len());
+    for m in FOOTER_MAGIC {
+        if read_db(buf) != *m {
+            return None;
+        }
+    }
+    buf.seek(len - FOOTER_TRAILER_LEN);
+    let fhash = read_dd_le(buf);
+    let size = read_dd_le(buf) as usize;
+    if size > len - FOOTER_TRAILER_LEN {
+        return None;
+    }
+    let start = len - FOOTER_TRAILER_LEN - size;
+    buf.seek(start);
+
+    let (index, hash) = {
+        let mut checkbuf = ReadBufChecksum::new(buf, Checksum::Adler32);
+        let mut index = FileIndex::new();
+        let nblocks = read_varint(&mut checkbuf);
+        for _ in 0..nblocks {
+            if checkbuf.pos() >= len - FOOTER_TRAILER_LEN {
+                return None;
+            }
+            let offset = read_varint(&mut checkbuf);
+            let rows = read_varint(&mut checkbuf);
+            let mut columns = Vec::new();
+            for ct in &schema.types {
+                let nulls = read_varint(&mut checkbuf);
+                let min = read_stats_value(&mut checkbuf, ct)?;
+                let max = read_stats_value(&mut checkbuf, ct)?;
+                columns.push(ColumnStats::from_parts(nulls, min, max));
+            }
+            index.blocks.push(BlockIndex::from_parts(offset, rows, columns, vec![None; schema.len()]));
+        }
+        if checkbuf.pos() < len - FOOTER_TRAILER_LEN {
+            if read_db(&mut checkbuf) != FOOTER_BLOOM {
+                return None;
+            }
+            for block in index.blocks.iter_mut() {
+                for bloom in block.blooms.iter_mut() {
+                    let hashes = read_varint(&mut checkbuf);
+                    if hashes == 0 {
+                        continue;
+                    }
+                    let size = read_varint(&mut checkbuf);
+                    if size > len - FOOTER_TRAILER_LEN - checkbuf.pos() {
+                        return None;
+                    }
+                    let bits = (0..size).map(|_| read_db(&mut checkbuf)).collect();
+                    *bloom = Some(Bloom { hashes, bits });
+                }
+            }
+        }
+        (index, checkbuf.hash().unwrap())
+    };
+    if hash != fhash || buf.pos() != len - FOOTER_TRAILER_LEN {
+        return None;
+    }
+    Some((index, start))
+}
+
+// read a row written by schema_write. Files with dictionary encoded
+// columns have to be read with schema_read_row_dict, NeedsRowReader is
+// returned for them and for files written by a Writer
 pub fn schema_read_row<B: ReadBuf>(
-    mut buf: &mut B,
+    buf: &mut B,
+    values: &mut [ColumnValue],
+    schema: &Schema2,
+) -> Result<(), SchemaReadError> {
+    if has_dictionary(schema) {
+        return Err(SchemaReadError::NeedsRowReader);
+    }
+    schema_read_row_dict(buf, values, schema, &mut Dictionaries::new(schema))
+}
+
+// read a row, 'dicts' keeps the dictionaries between the rows of a file.
+// NeedsRowReader for files with blocks, sync records or an index
+pub fn schema_read_row_dict<B: ReadBuf>(
+    buf: &mut B,
+    values: &mut [ColumnValue],
+    schema: &Schema2,
+    dicts: &mut Dictionaries,
+) -> Result<(), SchemaReadError> {
+    if needs_writer(schema) {
+        return Err(SchemaReadError::NeedsRowReader);
+    }
+    read_row_with(buf, values, None, schema, dicts)
+}
+
+// read a row for the RowReader, which handles blocks and sync records.
+// With 'spans' the uncompressed strings of plain encoded columns are not
+// copied if the buffer can return them. Their values are Null and 'spans'
+// has their positions and lengths in the buffer, it is None for the
+// other columns
+pub(crate) fn read_row_with<B: ReadBuf>(
+    buf: &mut B,
     values: &mut [ColumnValue],
+    mut spans: Option<&mut [Option<(usize, usize)>]>,
     schema: &Schema2,
+    dicts: &mut Dictionaries,
 ) -> Result<(), SchemaReadError> {
+    if let Some(ref mut spans) = spans {
+        for span in spans.iter_mut() {
+            *span = None;
+        }
+    }
     if buf.past_eof() {
         return Result::Err(SchemaReadError::Eof);
     }
     // read null bytes
     let modulo = schema.len() % 8;
     let aligned_len = schema.len() + if modulo > 0 { 8 - modulo } else { 0 };
+    // start checksum
+    let mut checkbuf = ReadBufChecksum::<B>::new(buf, schema.checksum);
     for i in 0..(aligned_len/8) {
-        let hash = {
-            // start checksum
-            let mut adlerbuf = ReadBufAdler32::<B>::new(&mut buf);
-//            if !check(buf, 1) {
-//                return false;
-//            }
-            // read null byte giving the null state of next 8 values
-            let b = read_db(&mut adlerbuf);
-            if adlerbuf.past_eof() {
-                // if the first byte read fails report eof not unexpected_eof
-                return Result::Err(SchemaReadError::Eof);
-            }
-            // number of column remaining (0..8)
-            let jmax = min(8, schema.len() - i * 8);
+        // read null byte giving the null state of next 8 values
+        let b = read_db(&mut checkbuf);
+        if checkbuf.past_eof() {
+            // if the first byte read fails report eof not unexpected_eof
+            return Result::Err(SchemaReadError::Eof);
+        }
+        // number of column remaining (0..8)
+        let jmax = min(8, schema.len() - i * 8);
 
-            for j in 0..jmax {
-                let bit = 1 << j;
-                if b & bit != 0 { // null bit set
-                    values[i * 8 + j] = ColumnValue::Null;
-                } else {
-                    match schema.ctype(i * 8 + j) {
-                        ColumnType::U32le => {
-                            let v = read_dd_le(&mut adlerbuf);
-                            if adlerbuf.past_eof() {
-                                return Result::Err(SchemaReadError::UnexpectedEof);
-                            }
-                            values[i * 8 + j] = ColumnValue::U32 { v: v};
-                        },
-                        ColumnType::U64le => {
-                            let v = read_dq_le(&mut adlerbuf);
-                            if adlerbuf.past_eof() {
-                                return Result::Err(SchemaReadError::UnexpectedEof);
-                            }
-                            values[i * 8 + j] = ColumnValue::U64 { v: v};
-                        },
-                        ColumnType::String => {
-                            let v = read_varstring(&mut adlerbuf);
-                            match v {
-                                Ok(s) => {
-                                    values[i * 8 + j] = ColumnValue::String { v: s };
-                                }
-                                Err(e) => {
-                                    // decompression failed etc.
-                                    values[i * 8 + j] = ColumnValue::Null;
-                                    return Result::Err(e);
-                                }
+        let boolbyte = if has_bool_byte(schema, i * 8) {
+            let bb = read_db(&mut checkbuf);
+            if checkbuf.past_eof() {
+                return Result::Err(SchemaReadError::UnexpectedEof);
+            }
+            bb
+        } else {
+            0
+        };
+
+        for j in 0..jmax {
+            let bit = 1 << j;
+            if b & bit != 0 { // null bit set
+                values[i * 8 + j] = ColumnValue::Null;
+            } else if schema.types[i * 8 + j] == ColumnType::Bool {
+                values[i * 8 + j] = ColumnValue::Bool { v: boolbyte & bit != 0 };
+            } else {
+                let ct = &schema.types[i * 8 + j];
+                let r = match schema.encodings[i * 8 + j] {
+                    Encoding::Plain if *ct == ColumnType::String && spans.is_some() => {
+                        read_varstring_span(&mut checkbuf, schema.prepared_zstd_dictionary()).map(|(v, span)| {
+                            if let Some(ref mut spans) = spans {
+                                spans[i * 8 + j] = span;
                             }
-                        },
+                            v
+                        })
+                    },
+                    Encoding::Plain => read_value(&mut checkbuf, ct, schema.prepared_zstd_dictionary()),
+                    Encoding::Dictionary => read_dict_value(&mut checkbuf, dicts, i * 8 + j, schema.prepared_zstd_dictionary()),
+                    Encoding::Varint => read_varint_value(&mut checkbuf, ct),
+                };
+                match r {
+                    Ok(v) => {
+                        values[i * 8 + j] = v;
+                    }
+                    Err(e) => {
+                        // decompression failed etc.
+                        values[i * 8 + j] = ColumnValue::Null;
+                        return Result::Err(e);
                     }
                 }
             }
-            adlerbuf.hash()
-        };
-        let fhash = read_dd_le::<B>(&mut buf);
+        }
+        if schema.checksum_granularity == ChecksumGranularity::Row && i + 1 < aligned_len/8 {
+            continue;
+        }
+        if let Some(hash) = checkbuf.hash() {
+            let fhash = read_dd_le::<B>(checkbuf.get_mut());
+            if checkbuf.past_eof() {
+                return Result::Err(SchemaReadError::UnexpectedEof);
+            }
+            if hash != fhash {
+                return Result::Err(SchemaReadError::ChecksumError);
+            }
+        }
+        checkbuf.reset();
+    }
+    Result::Ok(())
+}
+
+// move past a row like read_row_with without making its values, only the
+// dictionaries are kept up to date. The checksums are not checked
+pub(crate) fn skip_row_with<B: ReadBuf>(
+    buf: &mut B,
+    schema: &Schema2,
+    dicts: &mut Dictionaries,
+) -> Result<(), SchemaReadError> {
+    if buf.past_eof() {
+        return Result::Err(SchemaReadError::Eof);
+    }
+    let ngroups = schema.len().div_ceil(8);
+    for i in 0..ngroups {
+        let b = read_db(buf);
         if buf.past_eof() {
-            return Result::Err(SchemaReadError::UnexpectedEof);
+            return Result::Err(SchemaReadError::Eof);
         }
-        if hash != fhash {
-            return Result::Err(SchemaReadError::ChecksumError);
+        let jmax = min(8, schema.len() - i * 8);
+        if has_bool_byte(schema, i * 8) {
+            read_db(buf);
+        }
+        for j in 0..jmax {
+            let ct = &schema.types[i * 8 + j];
+            if b & (1 << j) != 0 || *ct == ColumnType::Bool {
+                continue; // in the null byte or the bool byte
+            }
+            match schema.encodings[i * 8 + j] {
+                Encoding::Plain => skip_value(buf, ct)?,
+                Encoding::Dictionary => skip_dict_value(buf, dicts, i * 8 + j, schema.prepared_zstd_dictionary())?,
+                Encoding::Varint => {
+                    read_varint64(buf);
+                },
+            }
+        }
+        if schema.checksum_granularity == ChecksumGranularity::Row && i + 1 < ngroups {
+            continue;
+        }
+        if schema.checksum != Checksum::None {
+            read_dd_le(buf);
         }
     }
+    if buf.past_eof() {
+        return Result::Err(SchemaReadError::UnexpectedEof);
+    }
     Result::Ok(())
 }
 
 fn schema_write_row<B: AppendBuf>(
-    mut buf: &mut B,
+    buf: &mut B,
     values: &[ColumnValue],
+    schema: &Schema2,
+    dicts: &mut Dictionaries,
+    options: &WriteOptions,
 ) {
-    for i in 0..(values.len() + 7)/8 {
-        let hash = {
-            let mut adlerbuf = AppendBufAdler32::<B>::new(&mut buf);
+    let mut checkbuf = AppendBufChecksum::<B>::new(buf, schema.checksum);
+    let ngroups = (values.len() + 7)/8;
+    for i in 0..ngroups {
+        let jmax = min(8, values.len() - i * 8);
 
-            let jmax = min(8, values.len() - i * 8);
+        // write the nullbyte for 8 next values
+        let mut nullbyte = 0 as u8;
+        for j in 0..jmax {
+            if values[i * 8 + j] == ColumnValue::Null {
+                nullbyte |= (1 << j) as u8;
+            }
+        }
+        write_db(&mut checkbuf, nullbyte);
 
-            // write the nullbyte for 8 next values
-            let mut nullbyte = 0 as u8;
+        // bool values are packed into a byte of their own
+        if has_bool_byte(schema, i * 8) {
+            let mut boolbyte = 0 as u8;
             for j in 0..jmax {
-                if values[i * 8 + j] == ColumnValue::Null {
-                    nullbyte |= (1 << j) as u8;
+                if values[i * 8 + j] == (ColumnValue::Bool { v: true }) {
+                    boolbyte |= (1 << j) as u8;
                 }
             }
-            write_db(&mut adlerbuf, nullbyte);
+            write_db(&mut checkbuf, boolbyte);
+        }
 
-            for j in 0..jmax {
-                match &values[i * 8 + j] {
-                    &ColumnValue::Null => {
-                        // taken care of by the null bytes
-                    },
-                    &ColumnValue::U32 { v } => {
-                        write_dd_le(&mut adlerbuf, v);
-                    },
-                    &ColumnValue::U64 { v } => {
-                        write_dq_le(&mut adlerbuf, v);
-                    },
-                    &ColumnValue::String { ref v } => {
-                        write_varstring(&mut adlerbuf, v);
-                    },
-                }
+        for j in 0..jmax {
+            match &values[i * 8 + j] {
+                &ColumnValue::Null => {
+                    // taken care of by the null bytes
+                },
+                &ColumnValue::Bool { .. } => {
+                    // taken care of by the bool byte
+                },
+                v => {
+                    let compression = schema.compression(i * 8 + j).unwrap_or(options.compression);
+                    match (schema.encodings[i * 8 + j], v) {
+                        (Encoding::Dictionary, ColumnValue::String { v }) => {
+                            write_dict_value(&mut checkbuf, dicts, i * 8 + j, v, &compression, schema.prepared_zstd_dictionary());
+                        },
+                        (Encoding::Varint, v) => {
+                            write_varint_value(&mut checkbuf, v);
+                        },
+                        (_, v) => {
+                            write_value(&mut checkbuf, &schema.types[i * 8 + j], v, &compression, schema.prepared_zstd_dictionary());
+                        },
+                    }
+                },
             }
-            adlerbuf.hash()
-        };
-        write_dd_le(buf, hash);
+        }
+        if schema.checksum_granularity == ChecksumGranularity::Row && i + 1 < ngroups {
+            continue;
+        }
+        if let Some(hash) = checkbuf.hash() {
+            write_dd_le(checkbuf.get_mut(), hash);
+        }
+        checkbuf.reset();
     }
 }
 
@@ -494,6 +1712,14 @@ fn test_varint() {
         let v: usize = read_varint(&mut sb);
         assert!(u == v);
     }
+    {
+        sb.seek(0);
+        let u: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+        write_varint64(&mut sb, u);
+        sb.seek(0);
+        let v: u64 = read_varint64(&mut sb);
+        assert!(u == v);
+    }
     {
         sb.seek(0);
         let u: u16 = 0x55AA;
@@ -526,13 +1752,37 @@ fn test_varstring() {
     {
         sb.seek(0);
         let u = "hello_world";
-        write_varstring(&mut sb, u);
+        write_varstring(&mut sb, u, &Compression::new());
         sb.seek(0);
         let v = read_varstring(&mut sb);
         assert!(u == v.unwrap());
     }
 }
 
+#[test]
+fn test_varbytes() {
+    let mut sb = Vecbuf::new(1024);
+    {
+        sb.seek(0);
+        // not valid utf-8
+        let u: Vec<u8> = vec![0xff, 0xfe, 0x00, 0x80, 0xc3];
+        write_varbytes(&mut sb, &u, &Compression::new());
+        sb.seek(0);
+        assert!(read_varstring(&mut sb).is_err());
+        sb.seek(0);
+        let v = read_varbytes(&mut sb);
+        assert!(u == v.unwrap());
+    }
+    {
+        sb.seek(0);
+        let u: Vec<u8> = (0..200).map(|n| (n % 4) as u8 | 0x80).collect();
+        write_varbytes(&mut sb, &u, &Compression::new());
+        sb.seek(0);
+        let v = read_varbytes(&mut sb);
+        assert!(u == v.unwrap());
+    }
+}
+
 #[test]
 fn test_schema_rw() {
     let mut s = Schema2::new();
@@ -564,6 +1814,222 @@ fn test_schema_rw() {
     assert!(sch.name(1) == "second");
 }
 
+#[test]
+fn test_schema_metadata() {
+    let mut s = Schema2::new();
+    s.add("id", ColumnType::U64le, false);
+    s.add("time", ColumnType::Timestamp { unit: TimeUnit::Seconds }, false);
+    s.add("host", ColumnType::String, true);
+
+    let mut vb = Vecbuf::new(1024);
+    s.set_description(1, Some("time of the request"));
+    s.set_description(2, Some("host that served the request"));
+    s.set_metadata("producer", "collector 1.2");
+    s.set_metadata("created", "2019-06-01T00:00:00Z");
+
+    vb.seek(0);
+    write_schema_v2(&mut vb, &s);
+    let row = vec![
+        ColumnValue::U64 { v: 1 },
+        ColumnValue::Timestamp { v: 1559347200, unit: TimeUnit::Seconds },
+        ColumnValue::Null,
+    ];
+    assert!(schema_write(&mut vb, row.as_slice(), &s));
+
+    vb.seek(0);
+    let rs = read_schema_v2(&mut vb).unwrap();
+    assert!(rs.description(0).is_none());
+    assert!(rs.description(1) == Some("time of the request"));
+    assert!(rs.description(2) == Some("host that served the request"));
+    assert!(rs.get_metadata("producer") == Some("collector 1.2"));
+    assert!(rs.get_metadata("created") == Some("2019-06-01T00:00:00Z"));
+    assert!(rs.metadata.len() == 2);
+
+    let mut rrow = vec![ColumnValue::Null; 3];
+    assert!(schema_read_row(&mut vb, rrow.as_mut_slice(), &rs).is_ok());
+    assert!(row == rrow);
+}
+
+#[test]
+fn test_schema_header() {
+    let mut s = Schema2::new();
+    s.add("id", ColumnType::U64le, false);
+    s.add("country", ColumnType::String, true);
+    s.set_encoding(1, Encoding::Dictionary);
+
+    let mut vb = Vecbuf::new(1024);
+    write_schema_v2(&mut vb, &s);
+    vb.seek(0);
+    for m in MAGIC {
+        assert!(read_db(&mut vb) == *m);
+    }
+    assert!(read_db(&mut vb) == FORMAT_VERSION);
+    assert!(read_dd_le(&mut vb) == FEATURE_DICTIONARY);
+
+    vb.seek(0);
+    let rs = read_schema_v2(&mut vb).unwrap();
+    assert!(rs.names == s.names);
+    assert!(rs.encodings == s.encodings);
+
+    // a corrupted column name fails the checksum
+    vb.seek(MAGIC.len() + 1 + 4 + 1 + 2);
+    vb.writeb(b'X');
+    vb.seek(0);
+    assert!(read_schema_v2(&mut vb).is_none());
+
+    // unknown feature flags are rejected
+    vb.seek(0);
+    write_schema_v2(&mut vb, &s);
+    vb.seek(MAGIC.len() + 1);
+    write_dd_le(&mut vb, 0x80000000 | FEATURE_DICTIONARY);
+    vb.seek(0);
+    assert!(read_schema_v2(&mut vb).is_none());
+
+    // files from a newer version are rejected
+    vb.seek(MAGIC.len());
+    vb.writeb(FORMAT_VERSION + 1);
+    vb.seek(0);
+    assert!(read_schema_v2(&mut vb).is_none());
+
+    // a file starting with something else is rejected
+    vb.seek(0);
+    for c in b"\x89PNG\r\n\x1a\n" {
+        vb.writeb(*c);
+    }
+    vb.seek(0);
+    assert!(read_schema_v2(&mut vb).is_none());
+}
+
+#[test]
+fn test_schema_header_legacy() {
+    let mut s = Schema2::new();
+    s.add("id", ColumnType::U64le, false);
+    s.add("host", ColumnType::String, true);
+
+    let row = vec![ColumnValue::U64 { v: 7 }, ColumnValue::String { v: "a".to_string() }];
+    let mut vb = Vecbuf::new(1024);
+    write_db(&mut vb, b'2');
+    write_schema_columns(&mut vb, &s, false);
+    assert!(schema_write(&mut vb, row.as_slice(), &s));
+
+    vb.seek(0);
+    let rs = read_schema_v2(&mut vb).unwrap();
+    assert!(rs.names == s.names);
+    assert!(rs.types == s.types);
+    let mut rrow = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vb, rrow.as_mut_slice(), &rs).is_ok());
+    assert!(row == rrow);
+
+    // version 3 is only written after the magic
+    vb.seek(0);
+    write_db(&mut vb, b'3');
+    vb.seek(0);
+    assert!(read_schema_v2(&mut vb).is_none());
+}
+
+#[test]
+fn test_schema_write_compression() {
+    let mut sch = Schema2::new();
+    sch.add("raw", ColumnType::String, false);
+    sch.add("archived", ColumnType::String, false);
+    sch.set_compression(1, Some(Compression::single(Codec::Zstd { level: 19 })));
+    sch.add("blob", ColumnType::Bytes, false);
+
+    let text = (0..100).map(|_| "compressible ").collect::<String>();
+    let row = vec![
+        ColumnValue::String { v: text.clone() },
+        ColumnValue::String { v: text.clone() },
+        ColumnValue::Bytes { v: text.clone().into_bytes() },
+    ];
+
+    let mut vbuf = Vecbuf::new(8192);
+    let options = WriteOptions::new().compression(Compression::none());
+    let mut dicts = Dictionaries::new(&sch);
+    assert!(schema_write_with(&mut vbuf, row.as_slice(), &sch, &mut dicts, &options));
+
+    // null byte, then the value markers
+    vbuf.seek(1);
+    assert!(read_db(&mut vbuf) == codec::MARKER_NONE);
+    vbuf.seek(1);
+    read_varstring(&mut vbuf).unwrap();
+    assert!(read_db(&mut vbuf) == b'Z');
+
+    vbuf.seek(0);
+    let mut rrow = vec![ColumnValue::Null; 3];
+    assert!(schema_read_row(&mut vbuf, rrow.as_mut_slice(), &sch).is_ok());
+    assert!(row == rrow);
+}
+
+#[test]
+fn test_schema_write_brotli() {
+    let mut sch = Schema2::new();
+    sch.add("text", ColumnType::String, false);
+    sch.add("words", ColumnType::List { inner: Box::new(ColumnType::String) }, false);
+
+    let text = (0..200).map(|n| format!("line {} of a long archived text\n", n % 7)).collect::<String>();
+    let row = vec![
+        ColumnValue::String { v: text.clone() },
+        ColumnValue::List { v: vec![ColumnValue::String { v: text.clone() }] },
+    ];
+
+    let mut vbuf = Vecbuf::new(8192);
+    let options = WriteOptions::new().compression(Compression::single(Codec::Brotli { quality: 9 }));
+    let mut dicts = Dictionaries::new(&sch);
+    assert!(schema_write_with(&mut vbuf, row.as_slice(), &sch, &mut dicts, &options));
+
+    vbuf.seek(1);
+    assert!(read_db(&mut vbuf) == b'B');
+    assert!(read_varint(&mut vbuf) < text.len() / 4);
+
+    vbuf.seek(0);
+    let mut rrow = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vbuf, rrow.as_mut_slice(), &sch).is_ok());
+    assert!(row == rrow);
+}
+
+#[test]
+fn test_schema_write_zstd_dictionary() {
+    let mut sch = Schema2::new();
+    sch.add("event", ColumnType::String, false);
+    sch.add("raw", ColumnType::Bytes, true);
+
+    let events: Vec<String> = (0..2000)
+        .map(|n| format!("{{\"user\":{},\"action\":\"view\",\"page\":\"/item/{}\"}}", n, n % 89))
+        .collect();
+    let dictionary = codec::train_zstd_dictionary(&events, 4096).unwrap();
+    sch.set_zstd_dictionary(Some(dictionary.clone()));
+
+    let mut vbuf = Vecbuf::new(16384);
+    write_schema_v2(&mut vbuf, &sch);
+    let options = WriteOptions::new().compression(Compression::single(Codec::ZstdDict { level: 3 }));
+    let mut dicts = Dictionaries::new(&sch);
+    let rows: Vec<Vec<ColumnValue>> = events[100..110].iter().map(|e| vec![
+        ColumnValue::String { v: e.clone() },
+        ColumnValue::Bytes { v: e.clone().into_bytes() },
+    ]).collect();
+    for row in &rows {
+        assert!(schema_write_with(&mut vbuf, row.as_slice(), &sch, &mut dicts, &options));
+    }
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.zstd_dictionary() == Some(dictionary.as_slice()));
+    let mut rrow = vec![ColumnValue::Null; 2];
+    for row in &rows {
+        assert!(schema_read_row(&mut vbuf, rrow.as_mut_slice(), &rsch).is_ok());
+        assert!(*row == rrow);
+    }
+
+    // the values can not be read without the dictionary
+    vbuf.seek(0);
+    let mut nodict = read_schema_v2(&mut vbuf).unwrap();
+    nodict.set_zstd_dictionary(None);
+    match schema_read_row(&mut vbuf, rrow.as_mut_slice(), &nodict) {
+        Err(SchemaReadError::DecompressionError) => {},
+        _ => panic!("expected DecompressionError"),
+    }
+}
+
 #[test]
 fn test_schema_write() {
     {
@@ -618,6 +2084,519 @@ fn test_schema_write() {
     }
 }
 
+#[test]
+fn test_schema_write_signed() {
+    let mut sch = Schema2::new();
+    sch.add("delta", ColumnType::I32le, false);
+    sch.add("balance", ColumnType::I64le, true);
+    sch.add("offset", ColumnType::I64le, true);
+
+    let vec = vec![
+        ColumnValue::I32 { v: -123456 },
+        ColumnValue::I64 { v: i64::min_value() },
+        ColumnValue::Null,
+    ];
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    // a signed column does not accept an unsigned value
+    let bad = vec![
+        ColumnValue::U32 { v: 1 },
+        ColumnValue::Null,
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::I32le);
+    assert!(rsch.ctype(1) == ColumnType::I64le);
+
+    let mut rvec = vec![ColumnValue::Null; 3];
+    let rr = schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch);
+    assert!(rr.is_ok());
+    assert!(vec == rvec);
+}
+
+#[test]
+fn test_schema_write_float() {
+    let mut sch = Schema2::new();
+    sch.add("score", ColumnType::F32le, false);
+    sch.add("measurement", ColumnType::F64le, true);
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let vec = vec![
+        ColumnValue::F32 { v: -1.5 },
+        ColumnValue::F64 { v: 6.02214076e23 },
+    ];
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+    let nan = vec![
+        ColumnValue::F32 { v: 0.0 },
+        ColumnValue::F64 { v: ::std::f64::NAN },
+    ];
+    assert!(schema_write(&mut vbuf, nan.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::F32le);
+    assert!(rsch.ctype(1) == ColumnType::F64le);
+
+    let mut rvec = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    match rvec[1] {
+        ColumnValue::F64 { v } => assert!(v.is_nan()),
+        _ => panic!("expected f64"),
+    }
+}
+
+#[test]
+fn test_schema_write_bool() {
+    let mut sch = Schema2::new();
+    for n in 0..10 {
+        sch.add(&format!("flag{}", n), ColumnType::Bool, n % 3 == 0);
+    }
+    sch.add("count", ColumnType::U32le, false);
+
+    let mut vec = Vec::new();
+    for n in 0..10 {
+        if n % 3 == 0 {
+            vec.push(ColumnValue::Null);
+        } else {
+            vec.push(ColumnValue::Bool { v: n % 2 == 0 });
+        }
+    }
+    vec.push(ColumnValue::U32 { v: 7 });
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::Bool);
+
+    let mut rvec = vec![ColumnValue::Null; 11];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+}
+
+#[test]
+fn test_schema_write_timestamp() {
+    let mut sch = Schema2::new();
+    sch.add("time", ColumnType::Timestamp { unit: TimeUnit::Millis }, false);
+    sch.set_timezone(0, Some("Europe/Prague"));
+    sch.add("seen", ColumnType::Timestamp { unit: TimeUnit::Nanos }, true);
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let vec = vec![
+        ColumnValue::Timestamp { v: 1546300800000, unit: TimeUnit::Millis },
+        ColumnValue::Timestamp { v: -1, unit: TimeUnit::Nanos },
+    ];
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    // the unit of the value has to match the column
+    let bad = vec![
+        ColumnValue::Timestamp { v: 1546300800, unit: TimeUnit::Seconds },
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::Timestamp { unit: TimeUnit::Millis });
+    assert!(rsch.timezone(0) == Some("Europe/Prague"));
+    assert!(rsch.ctype(1) == ColumnType::Timestamp { unit: TimeUnit::Nanos });
+    assert!(rsch.timezone(1).is_none());
+
+    let mut rvec = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+}
+
+#[test]
+fn test_schema_write_decimal() {
+    let mut sch = Schema2::new();
+    sch.add("amount", ColumnType::Decimal { precision: 12, scale: 2 }, false);
+    sch.add("total", ColumnType::Decimal { precision: 30, scale: 4 }, true);
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let vec = vec![
+        ColumnValue::Decimal { v: -123456789, scale: 2 },
+        ColumnValue::Decimal { v: 123456789012345678901234567, scale: 4 },
+    ];
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    // wrong scale
+    let bad = vec![
+        ColumnValue::Decimal { v: 1, scale: 3 },
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+    // too many digits for the precision
+    let bad = vec![
+        ColumnValue::Decimal { v: 1000000000000, scale: 2 },
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::Decimal { precision: 12, scale: 2 });
+    assert!(rsch.ctype(1) == ColumnType::Decimal { precision: 30, scale: 4 });
+
+    let mut rvec = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+}
+
+#[test]
+fn test_schema_write_fixed_binary() {
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::uuid(), false);
+    sch.add("digest", ColumnType::FixedBinary { size: 32 }, true);
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let vec = vec![
+        ColumnValue::Bytes { v: (0..16).collect() },
+        ColumnValue::Bytes { v: vec![0xab; 32] },
+    ];
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    // the size has to match exactly
+    let bad = vec![
+        ColumnValue::Bytes { v: vec![1; 15] },
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::FixedBinary { size: 16 });
+    assert!(rsch.ctype(1) == ColumnType::FixedBinary { size: 32 });
+
+    let mut rvec = vec![ColumnValue::Null; 2];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+}
+
+#[test]
+fn test_schema_write_list() {
+    let mut sch = Schema2::new();
+    sch.add("tags", ColumnType::List { inner: Box::new(ColumnType::String) }, false);
+    sch.add("ids", ColumnType::List { inner: Box::new(ColumnType::U64le) }, true);
+    sch.add("matrix", ColumnType::List {
+        inner: Box::new(ColumnType::List { inner: Box::new(ColumnType::Bool) })
+    }, true);
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let vec = vec![
+        ColumnValue::List { v: vec![
+            ColumnValue::String { v: "red".to_string() },
+            ColumnValue::String { v: "green".to_string() },
+        ] },
+        ColumnValue::List { v: vec![] },
+        ColumnValue::List { v: vec![
+            ColumnValue::List { v: vec![ColumnValue::Bool { v: true }] },
+            ColumnValue::List { v: vec![ColumnValue::Bool { v: false }, ColumnValue::Bool { v: true }] },
+        ] },
+    ];
+    assert!(schema_write(&mut vbuf, vec.as_slice(), &sch));
+
+    // elements have to match the inner type and can not be null
+    let bad = vec![
+        ColumnValue::List { v: vec![ColumnValue::Null] },
+        ColumnValue::Null,
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+    let bad = vec![
+        ColumnValue::List { v: vec![ColumnValue::U32 { v: 1 }] },
+        ColumnValue::Null,
+        ColumnValue::Null,
+    ];
+    assert!(!schema_write(&mut vbuf, bad.as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    for n in 0..3 {
+        assert!(rsch.ctype(n) == sch.ctype(n));
+    }
+
+    let mut rvec = vec![ColumnValue::Null; 3];
+    assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+    assert!(vec == rvec);
+
+    // deeply nested lists in a corrupt header are rejected
+    let nested = |depth: usize| {
+        let mut tags = vec![b'L'; depth];
+        tags.push(b'S');
+        let mut vb = Vecbuf::from_vec(tags);
+        let tag = read_db(&mut vb);
+        read_ctype_params(&mut vb, tag)
+    };
+    assert!(nested(MAX_LIST_DEPTH).is_some());
+    assert!(nested(MAX_LIST_DEPTH + 1).is_none());
+    assert!(nested(1_000_000).is_none());
+}
+
+#[test]
+fn test_schema_write_dictionary() {
+    let mut sch = Schema2::new();
+    sch.add("country", ColumnType::String, true);
+    sch.set_dictionary(0, true);
+    sch.add("city", ColumnType::String, false);
+
+    let rows: Vec<Vec<ColumnValue>> = ["cz", "de", "cz", "cz", "de", "at"].iter()
+        .enumerate()
+        .map(|(n, c)| vec![
+            if n == 3 { ColumnValue::Null } else { ColumnValue::String { v: c.to_string() } },
+            ColumnValue::String { v: format!("city{}", n) },
+        ])
+        .collect();
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let mut dicts = Dictionaries::new(&sch);
+    for row in &rows {
+        assert!(schema_write_with(&mut vbuf, row.as_slice(), &sch, &mut dicts, &WriteOptions::new()));
+    }
+    // appending with new dictionaries resets the dictionaries of the reader
+    let mut dicts = Dictionaries::new(&sch);
+    assert!(schema_write_with(&mut vbuf, rows[2].as_slice(), &sch, &mut dicts, &WriteOptions::new()));
+    assert!(schema_write_with(&mut vbuf, rows[4].as_slice(), &sch, &mut dicts, &WriteOptions::new()));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    assert!(rsch.ctype(0) == ColumnType::String);
+    assert!(rsch.encodings == vec![Encoding::Dictionary, Encoding::Plain]);
+
+    let mut rdicts = Dictionaries::new(&rsch);
+    let mut rvec = vec![ColumnValue::Null; 2];
+    for row in rows.iter().chain(vec![&rows[2], &rows[4]]) {
+        assert!(schema_read_row_dict(&mut vbuf, rvec.as_mut_slice(), &rsch, &mut rdicts).is_ok());
+        assert!(*row == rvec);
+    }
+
+    // an index into a dictionary the reader has not seen
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    let mut rdicts = Dictionaries::new(&rsch);
+    assert!(schema_read_row_dict(&mut vbuf, rvec.as_mut_slice(), &rsch, &mut rdicts).is_ok());
+    rdicts = Dictionaries::new(&rsch);
+    assert!(schema_read_row_dict(&mut vbuf, rvec.as_mut_slice(), &rsch, &mut rdicts).is_ok());
+    match schema_read_row_dict(&mut vbuf, rvec.as_mut_slice(), &rsch, &mut Dictionaries::new(&rsch)) {
+        Err(SchemaReadError::BadDictionaryIndex) => {},
+        _ => panic!("expected BadDictionaryIndex"),
+    }
+
+    // the functions without dictionaries refuse the schema
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    match schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch) {
+        Err(SchemaReadError::NeedsRowReader) => {},
+        _ => panic!("expected NeedsRowReader"),
+    }
+    assert!(!schema_write(&mut vbuf, rows[0].as_slice(), &sch));
+}
+
+#[test]
+fn test_schema_write_needs_writer() {
+    use v2::codec::BlockOptions;
+    use v2::sync::SyncOptions;
+
+    let mut sch = Schema2::new();
+    sch.add("id", ColumnType::U64le, false);
+    let row = vec![ColumnValue::U64 { v: 1 }];
+    let mut framed = vec![sch.clone(), sch.clone(), sch.clone()];
+    framed[0].set_blocks(Some(BlockOptions::new()));
+    framed[1].set_sync(Some(SyncOptions::new(100)));
+    framed[2].set_index(Some(100));
+    for s in &framed {
+        let mut vbuf = Vecbuf::new(1024);
+        assert!(!schema_write(&mut vbuf, row.as_slice(), s));
+        assert!(!schema_write_with(&mut vbuf, row.as_slice(), s, &mut Dictionaries::new(s), &WriteOptions::new()));
+        vbuf.seek(0);
+        let mut rrow = vec![ColumnValue::Null];
+        match schema_read_row(&mut vbuf, rrow.as_mut_slice(), s) {
+            Err(SchemaReadError::NeedsRowReader) => {},
+            _ => panic!("expected NeedsRowReader"),
+        }
+    }
+}
+
+#[test]
+fn test_schema_write_varint() {
+    let mut sch = Schema2::new();
+    sch.add("count", ColumnType::U32le, false);
+    sch.add("total", ColumnType::U64le, false);
+    sch.add("delta", ColumnType::I32le, true);
+    sch.add("offset", ColumnType::I64le, false);
+    sch.add("fixed", ColumnType::I64le, false);
+    for n in 0..4 {
+        sch.set_encoding(n, Encoding::Varint);
+    }
+
+    let rows = vec![
+        vec![
+            ColumnValue::U32 { v: 1 },
+            ColumnValue::U64 { v: 300 },
+            ColumnValue::I32 { v: -1 },
+            ColumnValue::I64 { v: 64 },
+            ColumnValue::I64 { v: -1 },
+        ],
+        vec![
+            ColumnValue::U32 { v: u32::max_value() },
+            ColumnValue::U64 { v: u64::max_value() },
+            ColumnValue::Null,
+            ColumnValue::I64 { v: i64::min_value() },
+            ColumnValue::I64 { v: i64::max_value() },
+        ],
+    ];
+
+    let mut vbuf = Vecbuf::new(1024);
+    write_schema_v2(&mut vbuf, &sch);
+    let start = vbuf.pos();
+    assert!(schema_write(&mut vbuf, rows[0].as_slice(), &sch));
+    // null byte, 1 + 2 + 1 + 2 bytes of varints, 8 bytes fixed, checksum
+    assert!(vbuf.pos() - start == 1 + 6 + 8 + 4);
+    assert!(schema_write(&mut vbuf, rows[1].as_slice(), &sch));
+
+    vbuf.seek(0);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    for n in 0..5 {
+        assert!(rsch.ctype(n) == sch.ctype(n));
+        assert!(rsch.encoding(n) == sch.encoding(n));
+    }
+
+    let mut rvec = vec![ColumnValue::Null; 5];
+    for row in &rows {
+        assert!(schema_read_row(&mut vbuf, rvec.as_mut_slice(), &rsch).is_ok());
+        assert!(*row == rvec);
+    }
+}
+
+#[test]
+fn test_schema_write_checksum() {
+    let mut sch = Schema2::new();
+    for n in 0..20 {
+        sch.add(&format!("c{}", n), ColumnType::U32le, true);
+    }
+    let row: Vec<ColumnValue> = (0..20).map(|n| ColumnValue::U32 { v: n }).collect();
+
+    let mut sizes = Vec::new();
+    for checksum in &[Checksum::None, Checksum::Adler32, Checksum::Crc32c, Checksum::XxHash32] {
+        for granularity in &[ChecksumGranularity::Group, ChecksumGranularity::Row] {
+            sch.set_checksum(*checksum, *granularity);
+            let mut out: Vec<u8> = Vec::new();
+            write_schema_v2(&mut out, &sch);
+            let header_len = out.len();
+            assert!(schema_write(&mut out, row.as_slice(), &sch));
+            sizes.push(out.len() - header_len);
+
+            let mut vb = Vecbuf::from_vec(out.clone());
+            let rsch = read_schema_v2(&mut vb).unwrap();
+            assert!(rsch.checksum == *checksum && rsch.checksum_granularity == *granularity);
+            let mut rrow = vec![ColumnValue::Null; 20];
+            assert!(schema_read_row(&mut vb, rrow.as_mut_slice(), &rsch).is_ok());
+            assert!(row == rrow);
+
+            // a damaged value in the first group
+            out[header_len + 1] ^= 0x40;
+            let mut vb = Vecbuf::from_vec(out);
+            let rsch = read_schema_v2(&mut vb).unwrap();
+            let r = schema_read_row(&mut vb, rrow.as_mut_slice(), &rsch);
+            if *checksum == Checksum::None {
+                assert!(r.is_ok() && rrow[0] != row[0]);
+            } else {
+                match r {
+                    Err(SchemaReadError::ChecksumError) => {},
+                    _ => panic!("expected a checksum error"),
+                }
+            }
+        }
+    }
+    // 3 groups of 8 columns, 4 bytes for each checksum
+    assert!(sizes[0] == sizes[1]);
+    assert!(sizes[2] == sizes[0] + 3 * 4);
+    assert!(sizes[3] == sizes[0] + 4);
+    assert!(sizes[4] == sizes[2] && sizes[6] == sizes[2]);
+
+    // the default needs no header feature, so older readers can read the file
+    sch.set_checksum(Checksum::Adler32, ChecksumGranularity::Group);
+    assert!(schema_features(&sch) & FEATURE_CHECKSUM == 0);
+}
+
+#[test]
+fn test_skip_row() {
+    let mut sch = Schema2::new();
+    sch.add("country", ColumnType::String, true);
+    sch.set_dictionary(0, true);
+    sch.add("id", ColumnType::U32le, false);
+    sch.add("count", ColumnType::I64le, true);
+    sch.set_encoding(2, Encoding::Varint);
+    sch.add("amount", ColumnType::Decimal { precision: 30, scale: 2 }, true);
+    sch.add("digest", ColumnType::FixedBinary { size: 4 }, true);
+    sch.add("tags", ColumnType::List { inner: Box::new(ColumnType::String) }, false);
+    sch.add("flag", ColumnType::Bool, false);
+    sch.add("at", ColumnType::Timestamp { unit: TimeUnit::Millis }, true);
+    sch.add("path", ColumnType::String, true);
+    sch.set_checksum(Checksum::Crc32c, ChecksumGranularity::Row);
+
+    let rows: Vec<Vec<ColumnValue>> = (0..20u32).map(|n| vec![
+        ColumnValue::String { v: ["cz", "de", "at"][n as usize % 3].to_string() },
+        ColumnValue::U32 { v: n },
+        if n % 4 == 0 { ColumnValue::Null } else { ColumnValue::I64 { v: -(n as i64) * 1000 } },
+        ColumnValue::Decimal { v: n as i128 * 12345, scale: 2 },
+        if n % 5 == 0 { ColumnValue::Null } else { ColumnValue::Bytes { v: vec![n as u8; 4] } },
+        ColumnValue::List { v: (0..n % 3).map(|t| ColumnValue::String { v: format!("tag{}", t) }).collect() },
+        ColumnValue::Bool { v: n % 2 == 0 },
+        ColumnValue::Timestamp { v: n as i64 * 1000, unit: TimeUnit::Millis },
+        ColumnValue::String { v: format!("/usr/share/doc/{}", n) },
+    ]).collect();
+
+    let mut out: Vec<u8> = Vec::new();
+    write_schema_v2(&mut out, &sch);
+    let mut dicts = Dictionaries::new(&sch);
+    for row in &rows {
+        assert!(schema_write_with(&mut out, row.as_slice(), &sch, &mut dicts, &WriteOptions::new()));
+    }
+
+    // the skipped rows still add their strings to the dictionaries
+    let mut vbuf = Vecbuf::from_vec(out);
+    let rsch = read_schema_v2(&mut vbuf).unwrap();
+    let mut rdicts = Dictionaries::new(&rsch);
+    let mut rvec = vec![ColumnValue::Null; 9];
+    for (n, row) in rows.iter().enumerate() {
+        if n % 3 == 2 {
+            assert!(schema_read_row_dict(&mut vbuf, rvec.as_mut_slice(), &rsch, &mut rdicts).is_ok());
+            assert!(*row == rvec);
+        } else {
+            assert!(skip_row_with(&mut vbuf, &rsch, &mut rdicts).is_ok());
+        }
+    }
+    match skip_row_with(&mut vbuf, &rsch, &mut rdicts) {
+        Err(SchemaReadError::Eof) => {},
+        _ => panic!("expected Eof"),
+    }
+}
+
+#[test]
+fn test_zigzag() {
+    for v in vec![0, -1, 1, -2, 2, i64::min_value(), i64::max_value()] {
+        assert!(unzigzag(zigzag(v)) == v);
+    }
+    assert!(zigzag(-1) == 1);
+    assert!(zigzag(1) == 2);
+}
+
 #[test]
 fn test_string_rw() {
     let mut n = 1;
@@ -627,8 +2606,8 @@ fn test_string_rw() {
         {
             let mut f = File::create("/tmp/_string.dat").unwrap();
             let mut wf = FileBuf::new(f, 4);
-            write_varstring(&mut wf, x.as_str());
-            write_varstring(&mut wf, y.as_str());
+            write_varstring(&mut wf, x.as_str(), &Compression::new());
+            write_varstring(&mut wf, y.as_str(), &Compression::new());
         }
         {
             let mut f = File::open("/tmp/_string.dat").unwrap();
@@ -667,3 +2646,44 @@ fn test_string_rw() {
 //         assert!(s == z);
 //     }
 // }
+
+#[test]
+fn test_find_sync() {
+    use v2::mmapbuf::MmapBuf;
+
+    let sync = SyncOptions::new(1);
+    let mut data: Vec<u8> = Vec::new();
+    let mut records = Vec::new();
+    for n in 0..3 {
+        // copies of the first marker byte and a marker without its record
+        data.extend((0..100000).map(|i| if i % 1000 == 1 { sync.marker[0] } else { 0 }));
+        data.extend(sync.marker.iter());
+        let pos = data.len();
+        write_sync(&mut data, &sync, pos, n + 1);
+        records.push(pos);
+    }
+    data.extend(vec![sync.marker[0]; 10]);
+    let len = data.len();
+
+    let mut vb = Vecbuf::from_vec(data.clone());
+    assert!(find_sync(&mut vb, &sync, 0) == Some(1));
+    assert!(find_sync(&mut vb, &sync, records[0] + 1) == Some(2));
+    assert!(find_sync(&mut vb, &sync, records[2] + 1).is_none());
+    assert!(rfind_sync(&mut vb, &sync, 0, len) == Some(records[2]));
+    assert!(rfind_sync(&mut vb, &sync, 0, records[2]) == Some(records[1]));
+    assert!(rfind_sync(&mut vb, &sync, records[0] + 1, records[1]).is_none());
+
+    // the same through the slices of a mapped file
+    {
+        let mut f = File::create("/tmp/_find_sync.dat").unwrap();
+        ::std::io::Write::write_all(&mut f, &data).unwrap();
+    }
+    let mut m = MmapBuf::new(File::open("/tmp/_find_sync.dat").unwrap());
+    assert!(find_sync(&mut m, &sync, 0) == Some(1));
+    assert!(m.pos() == records[0] + SYNC_MARKER_LEN + 9);
+    assert!(find_sync(&mut m, &sync, records[0] + 1) == Some(2));
+    assert!(find_sync(&mut m, &sync, records[2] + 1).is_none());
+    assert!(rfind_sync(&mut m, &sync, 0, len) == Some(records[2]));
+    assert!(rfind_sync(&mut m, &sync, 0, records[2]) == Some(records[1]));
+    assert!(rfind_sync(&mut m, &sync, records[0] + 1, records[1]).is_none());
+}
@@ -0,0 +1,224 @@
+use types::ColumnValue;
+use v2::buf::AppendBuf;
+use v2::schema2::Schema2;
+use v2::codec::WriteOptions;
+use v2::dict::Dictionaries;
+use v2::write2::{write_schema_v2, write_row, row_matches, write_block, write_row_group, write_sync, write_footer};
+use v2::index::{BlockIndex, FileIndex};
+
+// counts the bytes written, for the offsets of the sync records
+struct CountBuf<B: AppendBuf> {
+    target: B,
+    count: usize,
+}
+
+impl<B: AppendBuf> AppendBuf for CountBuf<B> {
+    fn writeb(&mut self, u: u8) {
+        self.target.writeb(u);
+        self.count += 1;
+    }
+    fn flush(&mut self) {
+        self.target.flush();
+    }
+}
+
+// writes the rows of a file, keeping the state needed between rows
+pub struct Writer<B: AppendBuf> {
+    buf: CountBuf<B>,
+    schema: Schema2,
+    options: WriteOptions,
+    dicts: Dictionaries,
+    block: Vec<u8>,    // rows of the current block in block mode
+    block_rows: usize,
+    group: Vec<Vec<ColumnValue>>, // rows of the current row group in columnar mode
+    group_bytes: usize, // estimated size of the group
+    sync_rows: usize,  // rows since the last sync record
+    index: Option<FileIndex>, // written as the footer when the writer is dropped
+    entry: BlockIndex, // statistics of the rows since the last index block
+}
+
+impl<B: AppendBuf> Writer<B> {
+    // start a new file, the header is written immediately
+    pub fn create(buf: B, schema: &Schema2, options: WriteOptions) -> Writer<B> {
+        let mut w = Writer::new(buf, 0, schema, options);
+        write_schema_v2(&mut w.buf, schema);
+        w.entry.offset = w.buf.count;
+        if schema.index_rows.is_some() {
+            w.index = Some(FileIndex::new());
+        }
+        w
+    }
+
+    // continue a file of 'size' bytes with the schema read from its header,
+    // no footer is written unless the index of the file is passed to set_index
+    pub fn append(buf: B, size: usize, schema: &Schema2, options: WriteOptions) -> Writer<B> {
+        Writer::new(buf, size, schema, options)
+    }
+
+    // continue the index of a file being appended to, the caller has
+    // removed the footer from the file
+    pub fn set_index(&mut self, index: FileIndex) {
+        if self.schema.index_rows.is_some() {
+            // the header does not tell which columns have Bloom filters
+            if let Some(last) = index.blocks.last() {
+                self.schema.bloom = last.bloom_columns();
+                self.entry = BlockIndex::new(self.entry.offset, &self.schema.types, &self.schema.bloom);
+            }
+            self.index = Some(index);
+        }
+    }
+
+    fn new(buf: B, size: usize, schema: &Schema2, options: WriteOptions) -> Writer<B> {
+        Writer {
+            buf: CountBuf { target: buf, count: size },
+            schema: schema.clone(),
+            options,
+            dicts: Dictionaries::new(schema),
+            block: Vec::new(),
+            block_rows: 0,
+            group: Vec::new(),
+            group_bytes: 0,
+            sync_rows: 0,
+            index: None,
+            entry: BlockIndex::new(size, &schema.types, &schema.bloom),
+        }
+    }
+
+    pub fn schema(&self) -> &Schema2 {
+        &self.schema
+    }
+
+    pub fn options_mut(&mut self) -> &mut WriteOptions {
+        &mut self.options
+    }
+
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.buf.target
+    }
+
+    // false if the values do not match the schema
+    pub fn write(&mut self, values: &[ColumnValue]) -> bool {
+        match self.schema.blocks {
+            None => {
+                if !write_row(&mut self.buf, values, &self.schema, &mut self.dicts, &self.options) {
+                    return false;
+                }
+                self.add_to_index(values);
+                if let Some(rows) = self.schema.index_rows {
+                    if self.entry.rows >= rows {
+                        self.finish_entry();
+                    }
+                }
+                self.sync_rows += 1;
+                self.maybe_sync();
+                true
+            },
+            Some(blocks) => {
+                if self.schema.columnar {
+                    if !row_matches(values, &self.schema) {
+                        return false;
+                    }
+                    self.group_bytes += values.iter().map(value_size).sum::<usize>();
+                    self.group.push(values.to_vec());
+                } else if !write_row(&mut self.block, values, &self.schema, &mut self.dicts, &self.options) {
+                    return false;
+                }
+                self.add_to_index(values);
+                self.block_rows += 1;
+                if self.block_rows >= blocks.rows || self.block.len() + self.group_bytes >= blocks.bytes {
+                    self.flush_block();
+                }
+                true
+            }
+        }
+    }
+
+    // end the current block early, e.g. before flushing the file
+    pub fn flush_block(&mut self) {
+        if let Some(blocks) = self.schema.blocks {
+            if self.block_rows > 0 {
+                if self.schema.columnar {
+                    write_row_group(&mut self.buf, &self.group, &self.schema, &self.options, &blocks.codec);
+                } else {
+                    write_block(&mut self.buf, self.block_rows, &self.block, &blocks.codec, self.schema.prepared_zstd_dictionary());
+                }
+                // index blocks end at the first block that reaches index_rows
+                if let Some(rows) = self.schema.index_rows {
+                    if self.entry.rows >= rows {
+                        self.finish_entry();
+                    }
+                }
+                self.block.clear();
+                self.group.clear();
+                self.group_bytes = 0;
+                self.sync_rows += self.block_rows;
+                self.block_rows = 0;
+                self.maybe_sync();
+            }
+        }
+    }
+
+    fn add_to_index(&mut self, values: &[ColumnValue]) {
+        if self.index.is_some() {
+            self.entry.add(values);
+        }
+    }
+
+    // the next index block starts here
+    fn finish_entry(&mut self) {
+        if let Some(ref mut index) = self.index {
+            if self.entry.rows > 0 {
+                let next = BlockIndex::new(self.buf.count, &self.schema.types, &self.schema.bloom);
+                let mut entry = ::std::mem::replace(&mut self.entry, next);
+                entry.finish();
+                index.blocks.push(entry);
+                // a reader can skip the blocks before
+                self.dicts = Dictionaries::new(&self.schema);
+            }
+        }
+    }
+
+    // sync records are only written between blocks in block mode
+    fn maybe_sync(&mut self) {
+        if let Some(sync) = self.schema.sync {
+            if self.sync_rows >= sync.interval {
+                self.write_sync();
+            }
+        }
+    }
+
+    fn write_sync(&mut self) {
+        if let Some(sync) = self.schema.sync {
+            let offset = self.buf.count;
+            write_sync(&mut self.buf, &sync, offset, self.sync_rows);
+            self.sync_rows = 0;
+            // a reader can start at any record
+            self.dicts = Dictionaries::new(&self.schema);
+        }
+    }
+}
+
+// about the number of bytes of a value in a row group
+fn value_size(value: &ColumnValue) -> usize {
+    match value {
+        ColumnValue::Null | ColumnValue::Bool { .. } => 0,
+        ColumnValue::String { v } => v.len() + 2,
+        ColumnValue::Bytes { v } => v.len() + 2,
+        ColumnValue::List { v } => v.iter().map(value_size).sum::<usize>() + 1,
+        _ => 8,
+    }
+}
+
+impl<B: AppendBuf> Drop for Writer<B> {
+    fn drop(&mut self) {
+        self.flush_block();
+        self.finish_entry();
+        // so that rows lost at the end of the file are counted too
+        if self.sync_rows > 0 {
+            self.write_sync();
+        }
+        if let Some(ref index) = self.index {
+            write_footer(&mut self.buf, &self.schema, index);
+        }
+    }
+}